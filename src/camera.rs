@@ -1,6 +1,15 @@
+pub mod chase;
+pub mod path;
+pub mod target;
+
 use std::{f32::consts::PI, ops::Neg};
 
-use nalgebra_glm::{rotate_vec3, Vec3};
+use nalgebra_glm::{quat_angle_axis, quat_conjugate, quat_look_at, quat_rotate_vec3, Quat, Vec3};
+
+use path::CameraPose;
+
+/// The vertical field of view used by default, in radians.
+pub const DEFAULT_FOV: f32 = 45.0 * PI / 180.0;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -21,6 +30,9 @@ pub struct Camera {
 
     /// The amount of cumulative zoom deltas it currently has.
     accum_zoom: f32,
+
+    /// Vertical field of view of the camera, in radians.
+    pub fov: f32,
 }
 
 impl Camera {
@@ -38,9 +50,19 @@ impl Camera {
             has_changed: true,
             accum_zoom: 0.0,
             max_zoom,
+            fov: DEFAULT_FOV,
         }
     }
 
+    /// Moves the camera to the given pose, usually coming from a `CameraPath`.
+    pub fn apply_pose(&mut self, pose: &CameraPose) {
+        self.has_changed = true;
+        self.eye = pose.eye;
+        self.center = pose.center;
+        self.up = pose.up;
+        self.fov = pose.fov;
+    }
+
     pub fn modify_center_and_eye(&mut self, new_center: Vec3, new_eye: Vec3) {
         self.has_changed = true;
         self.center = new_center;
@@ -56,31 +78,47 @@ impl Camera {
         self.has_changed = true;
     }
 
+    /// Moves the camera sideways by a certain delta, positive values go to the right.
+    pub fn strafe(&mut self, delta: f32) {
+        let delta_vec = self.right() * delta;
+
+        self.eye += delta_vec;
+        self.center += delta_vec;
+        self.has_changed = true;
+    }
+
+    /// Moves the camera by a certain delta along its up vector.
+    pub fn elevate(&mut self, delta: f32) {
+        let delta_vec = self.up.normalize() * delta;
+
+        self.eye += delta_vec;
+        self.center += delta_vec;
+        self.has_changed = true;
+    }
+
     /// Makes the camera rotate it's center by a given yaw and pitch.
     ///
     /// * `delta_yaw`: Rotates the camera from left to right.
     /// * `delta_pitch`: Rotates the camera up and down.
     pub fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
         let radius_vector = self.eye - self.center;
-        let radius = radius_vector.magnitude();
-
-        let current_yaw = radius_vector.z.atan2(radius_vector.x);
 
         let radius_xz =
             (radius_vector.x * radius_vector.x + radius_vector.z * radius_vector.z).sqrt();
         let current_pitch = (-radius_vector.y).atan2(radius_xz);
-
-        let new_yaw = (current_yaw + delta_yaw) % (2.0 * PI);
-        let new_pitch = (current_pitch + delta_pitch).clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1);
-
-        let new_eye = self.center
-            + Vec3::new(
-                radius * new_yaw.cos() * new_pitch.cos(),
-                -radius * new_pitch.sin(),
-                radius * new_yaw.sin() * new_pitch.cos(),
-            );
-
-        self.eye = new_eye;
+        // Keep it between slightly below (-PI/2, PI/2), the yaw flips over the poles.
+        let delta_pitch =
+            (current_pitch + delta_pitch).clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1) - current_pitch;
+
+        // Pitching goes around the horizontal axis perpendicular to the radius.
+        let pitch_axis = Vec3::y()
+            .cross(&radius_vector)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(Vec3::x());
+        let rotation =
+            quat_angle_axis(-delta_yaw, &Vec3::y()) * quat_angle_axis(delta_pitch, &pitch_axis);
+
+        self.eye = self.center + quat_rotate_vec3(&rotation, &radius_vector);
         self.has_changed = true;
     }
 
@@ -89,19 +127,13 @@ impl Camera {
     /// * `direction`: The direction to move the camera by.
     /// * `rotation_speed`: The speed to which move the camera by.
     pub fn move_center(&mut self, direction: Vec3, rotation_speed: f32) {
-        let radius_vector = self.center - self.eye;
-        let radius = radius_vector.magnitude();
-
         let angle_x = direction.x * rotation_speed; // Adjust this factor to control rotation speed
         let angle_y = direction.y * rotation_speed;
 
-        let rotated = rotate_vec3(&radius_vector, angle_x, &Vec3::new(0.0, 1.0, 0.0));
-
-        let right = rotated.cross(&self.up).normalize();
-        let final_rotated = rotate_vec3(&rotated, angle_y, &right);
-
-        self.center = self.eye + final_rotated.normalize() * radius;
-        self.has_changed = true;
+        // Turns around the world Y axis, then looks up or down around the right of the camera.
+        let rotation =
+            quat_angle_axis(angle_x, &Vec3::y()) * quat_angle_axis(angle_y, &self.right());
+        self.rotate(&rotation);
     }
 
     /// Zooms in and zooms out the camera by a given delta.
@@ -179,4 +211,72 @@ impl Camera {
     pub fn direction(&self) -> Vec3 {
         (self.center - self.eye).normalize()
     }
+
+    /// Get's the orientation of the camera.
+    /// Rotating -Z by it gives the direction the camera is looking at.
+    pub fn orientation(&self) -> Quat {
+        quat_conjugate(&quat_look_at(&self.direction(), &self.up))
+    }
+
+    /// Rotates the camera in place by the given quaternion, the eye doesn't move.
+    pub fn rotate(&mut self, rotation: &Quat) {
+        let radius_vector = self.center - self.eye;
+
+        self.center = self.eye + quat_rotate_vec3(rotation, &radius_vector);
+        self.up = quat_rotate_vec3(rotation, &self.up).normalize();
+        self.has_changed = true;
+    }
+
+    /// Get's the vector pointing to the right of the camera, normalized.
+    pub fn right(&self) -> Vec3 {
+        self.direction().cross(&self.up).normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::{quat_angle_axis, vec3};
+
+    use super::*;
+    use crate::equal;
+
+    #[test]
+    fn test_orientation_matches_direction() {
+        let mut camera = Camera::new(
+            vec3(3.0, 2.0, 5.0),
+            vec3(-1.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            1.0,
+        );
+        let forward = quat_rotate_vec3(&camera.orientation(), &-Vec3::z());
+        assert!(equal((forward - camera.direction()).magnitude(), 0.0, 1e-5));
+
+        // A quarter turn to the left...
+        camera.rotate(&quat_angle_axis(PI / 2.0, &Vec3::y()));
+        let forward = quat_rotate_vec3(&camera.orientation(), &-Vec3::z());
+        assert!(equal((forward - camera.direction()).magnitude(), 0.0, 1e-5));
+    }
+
+    #[test]
+    fn test_orbit_keeps_the_radius() {
+        let mut camera = Camera::new(
+            vec3(10.0, 0.0, 0.0),
+            Vec3::zeros(),
+            vec3(0.0, 1.0, 0.0),
+            1.0,
+        );
+
+        // Positive yaw goes from the X axis towards the Z axis...
+        camera.orbit(PI / 2.0, 0.0);
+        assert!(equal(
+            (camera.eye - vec3(0.0, 0.0, 10.0)).magnitude(),
+            0.0,
+            1e-4
+        ));
+
+        // ...and the pitch stops before going over the top.
+        camera.orbit(0.0, -PI);
+        assert!(equal(camera.eye.magnitude(), 10.0, 1e-4));
+        assert!(camera.eye.y > 9.0 && camera.eye.y < 10.0);
+    }
 }
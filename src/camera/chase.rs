@@ -0,0 +1,110 @@
+use nalgebra_glm::{quat_rotate_vec3, quat_rotation, quat_slerp, Quat, Vec3};
+
+use crate::Ship;
+
+use super::Camera;
+
+/// The biggest time step (in seconds) the spring is allowed to take,
+/// bigger steps make the spring explode.
+const MAX_STEP: f32 = 0.1;
+
+/// A camera controller that follows the ship from behind using a damped spring.
+///
+/// All offsets are expressed relative to the ship orientation:
+/// `x` goes to the right of the ship, `y` goes up and `z` goes backwards.
+#[derive(Debug, Clone, Copy)]
+pub struct ChaseCamera {
+    /// Where the eye of the camera wants to be relative to the ship.
+    pub offset: Vec3,
+    /// The point the camera looks at relative to the ship.
+    pub look_offset: Vec3,
+    /// How strong the spring pulls the eye towards its target.
+    pub stiffness: f32,
+    /// How much the spring resists moving, use `2 * sqrt(stiffness)` for a critically damped spring.
+    pub damping: f32,
+    /// Time in seconds the point being looked at takes to catch up with the ship.
+    /// Zero means no lag at all.
+    pub lag: f32,
+    /// How many frames worth of velocity the camera looks ahead of the ship.
+    pub look_ahead: f32,
+    /// Current velocity of the eye of the camera.
+    velocity: Vec3,
+}
+
+impl ChaseCamera {
+    /// Creates a critically damped chase camera with the given offsets.
+    ///
+    /// * `offset`: Where the eye wants to be relative to the ship.
+    /// * `look_offset`: What the camera looks at relative to the ship.
+    pub fn new(offset: Vec3, look_offset: Vec3) -> Self {
+        let stiffness = 40.0;
+
+        ChaseCamera {
+            offset,
+            look_offset,
+            stiffness,
+            damping: 2.0 * stiffness.sqrt(),
+            lag: 0.1,
+            look_ahead: 0.0,
+            velocity: Vec3::zeros(),
+        }
+    }
+
+    /// Computes the position the eye and center of the camera want to reach.
+    pub fn targets(&self, ship: &Ship) -> (Vec3, Vec3) {
+        let model = &ship.entity.model;
+        let (right, up, backward) = (model.right(), model.up(), -model.forward());
+        let to_world = |v: Vec3| right * v.x + up * v.y + backward * v.z;
+
+        let eye = model.translation + to_world(self.offset);
+        let center =
+            model.translation + to_world(self.look_offset) + ship.velocity * self.look_ahead;
+
+        (eye, center)
+    }
+
+    /// Moves the camera towards the ship.
+    ///
+    /// * `dt`: Milliseconds elapsed since the last update.
+    pub fn update(&mut self, camera: &mut Camera, ship: &Ship, dt: f32) {
+        let (target_eye, target_center) = self.targets(ship);
+        let mut remaining = (dt / 1000.0).max(0.0);
+
+        // The ship moves rigidly each frame, move the camera along so the spring
+        // only has to deal with the orientation and acceleration changes.
+        let mut eye = camera.eye + ship.velocity;
+        while remaining > 0.0 {
+            let step = remaining.min(MAX_STEP);
+            let acceleration = (target_eye - eye) * self.stiffness - self.velocity * self.damping;
+            self.velocity += acceleration * step;
+            eye += self.velocity * step;
+            remaining -= step;
+        }
+
+        let follow = if self.lag > 0.0 {
+            1.0 - (-dt / 1000.0 / self.lag).exp()
+        } else {
+            1.0
+        };
+        let center = camera.center + ship.velocity;
+        let center = center + (target_center - center) * follow;
+        // Rolls along the shortest arc towards the up of the ship.
+        let roll = quat_slerp(
+            &Quat::identity(),
+            &quat_rotation(&camera.up, &ship.entity.model.up()),
+            follow,
+        );
+
+        camera.modify_center_and_eye(center, eye);
+        camera.up = quat_rotate_vec3(&roll, &camera.up).normalize();
+    }
+
+    /// Places the camera on its target immediately, ignoring the spring.
+    pub fn snap(&mut self, camera: &mut Camera, ship: &Ship) {
+        let (eye, center) = self.targets(ship);
+
+        self.velocity = Vec3::zeros();
+        camera.modify_center_and_eye(center, eye);
+        camera.up = ship.entity.model.up();
+    }
+}
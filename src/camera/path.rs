@@ -0,0 +1,370 @@
+use std::ops::{Add, Mul, Sub};
+
+use nalgebra_glm::Vec3;
+
+use crate::Entity;
+
+use super::Camera;
+
+/// How the camera travels between the points defined by the keyframes.
+#[derive(Debug, Clone, Copy)]
+pub enum Interpolation {
+    /// Straight lines between keyframes.
+    Linear,
+    /// A curve that passes through every keyframe.
+    CatmullRom,
+    /// A single curve that starts and ends on the first and last keyframes,
+    /// every keyframe in between acts as a control point.
+    /// The easing of the first keyframe is applied to the whole curve.
+    Bezier,
+}
+
+/// Remaps the progress inside a segment of the path.
+#[derive(Debug, Clone, Copy)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /// Applies the easing to `t`, which should be between [0, 1].
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A point in the world a keyframe can refer to.
+#[derive(Debug, Clone, Copy)]
+pub enum Anchor {
+    /// A fixed point in world space.
+    Point(Vec3),
+    /// The translation of an entity (its index inside `Model::entities`) plus an offset.
+    /// Useful to follow a planet along its `Ellipsis`.
+    Entity { index: usize, offset: Vec3 },
+}
+
+impl Anchor {
+    /// Anchors to the first entity with that name.
+    /// If there's none, the offset is used as a world position.
+    pub fn entity_named(entities: &[Entity], name: &str, offset: Vec3) -> Self {
+        entities
+            .iter()
+            .position(|e| e.name.as_deref() == Some(name))
+            .map(|index| Anchor::Entity { index, offset })
+            .unwrap_or(Anchor::Point(offset))
+    }
+
+    /// Computes the world position of the anchor given the current state of the entities.
+    /// If the entity doesn't exist the offset is used as a world position.
+    pub fn resolve(&self, entities: &[Entity]) -> Vec3 {
+        match self {
+            Anchor::Point(point) => *point,
+            Anchor::Entity { index, offset } => entities
+                .get(*index)
+                .map(|e| e.model.translation + offset)
+                .unwrap_or(*offset),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraKeyframe {
+    /// The time of the keyframe in milliseconds, relative to the start of the path.
+    pub time: f32,
+    pub eye: Anchor,
+    pub center: Anchor,
+    pub up: Vec3,
+    /// Vertical field of view in radians.
+    pub fov: f32,
+    /// The easing used to go from this keyframe to the next one.
+    pub easing: Easing,
+}
+
+/// The state of the camera at a given time of the path.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraPose {
+    pub eye: Vec3,
+    pub center: Vec3,
+    pub up: Vec3,
+    pub fov: f32,
+}
+
+pub struct CameraPath {
+    keyframes: Vec<CameraKeyframe>,
+    pub interpolation: Interpolation,
+    /// If true, the path starts again once it reaches the end.
+    pub looping: bool,
+}
+
+impl CameraPath {
+    /// Creates a new path, the keyframes are sorted by their time.
+    pub fn new(
+        mut keyframes: Vec<CameraKeyframe>,
+        interpolation: Interpolation,
+        looping: bool,
+    ) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        CameraPath {
+            keyframes,
+            interpolation,
+            looping,
+        }
+    }
+
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    /// The time it takes to go through the whole path, in milliseconds.
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Computes the pose of the camera `time` milliseconds after the path started.
+    ///
+    /// Returns `None` if the path has no keyframes or if it already ended and isn't looping.
+    pub fn sample(&self, time: f32, entities: &[Entity]) -> Option<CameraPose> {
+        let first = self.keyframes.first()?;
+        let duration = self.duration();
+
+        let time = match (self.looping, duration > 0.0) {
+            (true, true) => time.rem_euclid(duration),
+            (false, _) if time > duration => return None,
+            _ => time.max(0.0),
+        } + first.time;
+
+        let resolve = |k: &CameraKeyframe| {
+            (
+                k.eye.resolve(entities),
+                k.center.resolve(entities),
+                k.up,
+                k.fov,
+            )
+        };
+
+        if self.keyframes.len() == 1 {
+            let (eye, center, up, fov) = resolve(first);
+            return Some(CameraPose {
+                eye,
+                center,
+                up,
+                fov,
+            });
+        }
+
+        let pose = match self.interpolation {
+            Interpolation::Bezier => {
+                let t = if duration > 0.0 {
+                    first.easing.apply((time - first.time) / duration)
+                } else {
+                    0.0
+                };
+                let points: Vec<_> = self.keyframes.iter().map(resolve).collect();
+
+                CameraPose {
+                    eye: de_casteljau(points.iter().map(|p| p.0).collect(), t),
+                    center: de_casteljau(points.iter().map(|p| p.1).collect(), t),
+                    up: de_casteljau(points.iter().map(|p| p.2).collect(), t),
+                    fov: de_casteljau(points.iter().map(|p| p.3).collect(), t),
+                }
+            }
+            Interpolation::Linear | Interpolation::CatmullRom => {
+                let last_idx = self.keyframes.len() - 1;
+                let segment = self
+                    .keyframes
+                    .windows(2)
+                    .position(|w| time <= w[1].time)
+                    .unwrap_or(last_idx - 1);
+
+                let start = &self.keyframes[segment];
+                let end = &self.keyframes[segment + 1];
+                let segment_duration = end.time - start.time;
+                let t = if segment_duration > 0.0 {
+                    start.easing.apply((time - start.time) / segment_duration)
+                } else {
+                    1.0
+                };
+
+                let p1 = resolve(start);
+                let p2 = resolve(end);
+
+                if matches!(self.interpolation, Interpolation::Linear) {
+                    CameraPose {
+                        eye: lerp(p1.0, p2.0, t),
+                        center: lerp(p1.1, p2.1, t),
+                        up: lerp(p1.2, p2.2, t),
+                        fov: lerp(p1.3, p2.3, t),
+                    }
+                } else {
+                    // The curve needs one extra point on each side,
+                    // at the borders the keyframe itself is used.
+                    let p0 = resolve(&self.keyframes[segment.saturating_sub(1)]);
+                    let p3 = resolve(&self.keyframes[(segment + 2).min(last_idx)]);
+
+                    CameraPose {
+                        eye: catmull_rom(p0.0, p1.0, p2.0, p3.0, t),
+                        center: catmull_rom(p0.1, p1.1, p2.1, p3.1, t),
+                        up: catmull_rom(p0.2, p1.2, p2.2, p3.2, t),
+                        fov: catmull_rom(p0.3, p1.3, p2.3, p3.3, t),
+                    }
+                }
+            }
+        };
+
+        Some(CameraPose {
+            up: pose.up.normalize(),
+            ..pose
+        })
+    }
+}
+
+/// Keeps track of a path that's currently being played.
+#[derive(Debug, Clone, Copy)]
+pub struct PathPlayback {
+    /// The simulation time in which the path started playing.
+    pub start_time: f32,
+    /// The camera before the path started, so it can be restored once it ends.
+    pub previous_camera: Camera,
+}
+
+impl PathPlayback {
+    pub fn new(start_time: f32, previous_camera: Camera) -> Self {
+        PathPlayback {
+            start_time,
+            previous_camera,
+        }
+    }
+
+    /// Samples the `path` using the given simulation time.
+    pub fn pose_at(&self, path: &CameraPath, time: f32, entities: &[Entity]) -> Option<CameraPose> {
+        path.sample(time - self.start_time, entities)
+    }
+}
+
+fn lerp<T>(a: T, b: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    a + (b - a) * t
+}
+
+/// Uniform Catmull-Rom spline between `p1` and `p2`.
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+/// Evaluates the Bézier curve defined by `points` at `t`.
+fn de_casteljau<T>(mut points: Vec<T>, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    while points.len() > 1 {
+        points = points.windows(2).map(|w| lerp(w[0], w[1], t)).collect();
+    }
+
+    points[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec3;
+
+    use super::*;
+    use crate::equal;
+    use crate::fragment::planets::{create_green_planet, create_sun};
+
+    fn keyframe(time: f32, eye: Vec3) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            eye: Anchor::Point(eye),
+            center: Anchor::Point(Vec3::zeros()),
+            up: vec3(0.0, 1.0, 0.0),
+            fov: 1.0,
+            easing: Easing::Linear,
+        }
+    }
+
+    #[test]
+    fn test_catmull_rom_passes_through_keyframes() {
+        let path = CameraPath::new(
+            vec![
+                keyframe(0.0, vec3(0.0, 0.0, 10.0)),
+                keyframe(100.0, vec3(10.0, 0.0, 0.0)),
+                keyframe(200.0, vec3(0.0, 5.0, -10.0)),
+            ],
+            Interpolation::CatmullRom,
+            false,
+        );
+
+        for k in path.keyframes() {
+            let pose = path.sample(k.time, &[]).unwrap();
+            let expected = k.eye.resolve(&[]);
+            assert!(equal((pose.eye - expected).magnitude(), 0.0, 1e-4));
+        }
+    }
+
+    #[test]
+    fn test_path_ends_when_not_looping() {
+        let path = CameraPath::new(
+            vec![
+                keyframe(0.0, vec3(0.0, 0.0, 10.0)),
+                keyframe(100.0, vec3(10.0, 0.0, 0.0)),
+            ],
+            Interpolation::Linear,
+            false,
+        );
+
+        assert!(path.sample(150.0, &[]).is_none());
+
+        let path = CameraPath {
+            looping: true,
+            ..path
+        };
+        let pose = path.sample(150.0, &[]).unwrap();
+        assert!(equal(pose.eye.x, 5.0, 1e-4));
+    }
+
+    #[test]
+    fn test_named_anchor_follows_the_entity() {
+        let mut green_planet = create_green_planet();
+        green_planet.model.translation = vec3(20.0, 0.0, 0.0);
+        let entities = vec![create_sun(Vec3::zeros()), green_planet];
+
+        let offset = vec3(0.0, 3.0, 0.0);
+        let anchor = Anchor::entity_named(&entities, "green_planet", offset);
+        assert!(equal(
+            (anchor.resolve(&entities) - vec3(20.0, 3.0, 0.0)).magnitude(),
+            0.0,
+            1e-4
+        ));
+
+        let missing = Anchor::entity_named(&entities, "gas_giant", offset);
+        assert!(equal(
+            (missing.resolve(&entities) - offset).magnitude(),
+            0.0,
+            1e-4
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+use nalgebra_glm::{smoothstep, Vec3};
+
+use crate::Entity;
+
+use super::Camera;
+
+/// Keeps the camera orbiting around one of the entities, following it along its orbit.
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitLock {
+    /// Index of the entity being followed inside `Model::entities`.
+    pub target: usize,
+    /// Milliseconds it takes for the camera to move from one target to another.
+    pub transition_duration: f32,
+    /// Point the camera was looking at when the target changed.
+    transition_from: Vec3,
+    /// Simulation time in which the target changed.
+    transition_start: f32,
+}
+
+impl OrbitLock {
+    /// Locks the camera to the given target, without any transition.
+    ///
+    /// * `target`: Index of the entity to follow.
+    /// * `transition_duration`: Milliseconds it takes to move to a new target.
+    pub fn new(target: usize, transition_duration: f32) -> Self {
+        OrbitLock {
+            target,
+            transition_duration,
+            transition_from: Vec3::zeros(),
+            transition_start: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Changes the target, the camera will move smoothly towards it.
+    ///
+    /// * `target`: Index of the new entity to follow.
+    /// * `camera`: The camera that's currently following the old target.
+    /// * `time`: The current simulation time.
+    pub fn retarget(&mut self, target: usize, camera: &Camera, time: f32) {
+        self.target = target;
+        self.transition_from = camera.center;
+        self.transition_start = time;
+    }
+
+    /// Cycles the target across the entities by the given step.
+    pub fn cycle(&mut self, step: isize, entity_count: usize, camera: &Camera, time: f32) {
+        if entity_count == 0 {
+            return;
+        }
+
+        let target = (self.target as isize + step).rem_euclid(entity_count as isize);
+        self.retarget(target as usize, camera, time);
+    }
+
+    /// Returns true while the camera is still moving towards a new target.
+    pub fn is_transitioning(&self, time: f32) -> bool {
+        time - self.transition_start < self.transition_duration
+    }
+
+    /// Moves the camera so it keeps looking at the target.
+    /// The eye moves along with the center, so the orbit radius and angle are kept.
+    pub fn update(&self, camera: &mut Camera, entities: &[Entity], time: f32) {
+        let Some(entity) = entities.get(self.target) else {
+            return;
+        };
+        let target_position = entity.model.translation;
+
+        let center = if self.is_transitioning(time) {
+            let progress = smoothstep(
+                0.0,
+                1.0,
+                (time - self.transition_start) / self.transition_duration,
+            );
+            self.transition_from + (target_position - self.transition_from) * progress
+        } else {
+            target_position
+        };
+
+        let delta = center - camera.center;
+        camera.modify_center_and_eye(center, camera.eye + delta);
+    }
+}
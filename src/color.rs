@@ -1,7 +1,12 @@
 pub mod blenders;
+pub mod ramp;
+
+use serde::{Deserialize, Serialize};
 
 /// Represents a Color to print in the screen.
-#[derive(Debug, Clone, Copy)]
+/// Gets saved as a hex string, like `"#ff7900"`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -126,6 +131,27 @@ impl From<Color> for u32 {
     }
 }
 
+impl From<Color> for String {
+    fn from(value: Color) -> Self {
+        format!("#{:06x}", to_hex_value(&value))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let digits = value.trim_start_matches('#');
+        if digits.len() != 6 {
+            return Err(format!("{value} isn't a color like #ff7900"));
+        }
+
+        u32::from_str_radix(digits, 16)
+            .map(|hex| from_hex_value(&hex))
+            .map_err(|_| format!("{value} isn't a color like #ff7900"))
+    }
+}
+
 impl std::ops::Add for Color {
     type Output = Color;
 
@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::color::Color;
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum BlendMode {
     Normal,
     Add,
@@ -0,0 +1,99 @@
+use nalgebra_glm::smoothstep;
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+
+/// How the colors of a `ColorRamp` change between two stops.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RampInterpolation {
+    Linear,
+    /// Eases in and out of each stop.
+    Smoothstep,
+    /// Keeps the color of the previous stop until the next one, good for banding.
+    Constant,
+}
+
+/// A color placed somewhere on a `ColorRamp`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ColorStop {
+    /// Where the stop is, usually from 0 to 1.
+    pub position: f32,
+    pub color: Color,
+}
+
+/// Maps values, like the ones of a noise, into colors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorRamp {
+    /// Don't need to be sorted.
+    pub stops: Vec<ColorStop>,
+    pub interpolation: RampInterpolation,
+}
+
+impl ColorRamp {
+    /// Gets the color of the ramp at the given value.
+    /// Values outside of the stops get the color of the closest stop.
+    pub fn sample(&self, value: f32) -> Color {
+        // The closest stops on each side of the value.
+        let below = self
+            .stops
+            .iter()
+            .filter(|stop| stop.position <= value)
+            .max_by(|a, b| a.position.total_cmp(&b.position));
+        let above = self
+            .stops
+            .iter()
+            .filter(|stop| stop.position > value)
+            .min_by(|a, b| a.position.total_cmp(&b.position));
+
+        match (below, above) {
+            (None, None) => Color::black(),
+            (Some(stop), None) | (None, Some(stop)) => stop.color,
+            (Some(below), Some(above)) => {
+                let t = (value - below.position) / (above.position - below.position);
+                match self.interpolation {
+                    RampInterpolation::Linear => below.color.lerp(&above.color, t),
+                    RampInterpolation::Smoothstep => {
+                        below.color.lerp(&above.color, smoothstep(0.0, 1.0, t))
+                    }
+                    RampInterpolation::Constant => below.color,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ramp_interpolation_modes() {
+        let stops = vec![
+            ColorStop {
+                position: 1.0,
+                color: Color::white(),
+            },
+            ColorStop {
+                position: 0.0,
+                color: Color::black(),
+            },
+        ];
+        let ramp = |interpolation| ColorRamp {
+            stops: stops.clone(),
+            interpolation,
+        };
+
+        let linear = ramp(RampInterpolation::Linear);
+        assert_eq!(linear.sample(0.5), Color::new(128, 128, 128));
+        assert_eq!(linear.sample(-1.0), Color::black());
+        assert_eq!(linear.sample(2.0), Color::white());
+
+        let smooth = ramp(RampInterpolation::Smoothstep);
+        assert!(smooth.sample(0.25).r < linear.sample(0.25).r);
+        assert_eq!(smooth.sample(0.5), Color::new(128, 128, 128));
+
+        let constant = ramp(RampInterpolation::Constant);
+        assert_eq!(constant.sample(0.99), Color::black());
+        assert_eq!(constant.sample(1.0), Color::white());
+    }
+}
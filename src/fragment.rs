@@ -1,10 +1,12 @@
+pub mod bake;
 pub mod ellipsis;
+pub mod lighting;
 pub mod material;
 pub mod planets;
 pub mod shaders;
 pub mod ship;
 
-use crate::{color::Color, light::Light, vertex::Vertex};
+use crate::{color::Color, light::Light, vertex::Vertex, viewport::Rect};
 use nalgebra_glm::{dot, vec2, vec3_to_vec2, Vec2, Vec3};
 
 pub struct Fragment {
@@ -14,6 +16,23 @@ pub struct Fragment {
     pub depth: f32,
     pub vertex_position: Vec3,
     pub texture_position: Vec2,
+    /// How much `texture_position` changes from this pixel to the next one on X.
+    /// Used to pick the mipmap level of the textures.
+    pub texture_dx: Vec2,
+    /// How much `texture_position` changes from this pixel to the next one on Y.
+    pub texture_dy: Vec2,
+    /// The position of the fragment in world space.
+    pub world_position: Vec3,
+    /// The interpolated normal of the fragment.
+    /// Zero if the fragment doesn't belong to a surface, like wireframe lines.
+    pub normal: Vec3,
+    /// The interpolated tangent of the fragment, used for normal mapping.
+    pub tangent: Vec3,
+    /// Light emitted by the fragment, used for bloom.
+    pub emission: Color,
+    /// How opaque the fragment is, from 0 to 1.
+    /// Only used by the fragments of transparent entities.
+    pub alpha: f32,
 }
 
 impl Fragment {
@@ -25,6 +44,13 @@ impl Fragment {
             vertex_position,
             intensity: 1.0,
             texture_position: Vec2::zeros(),
+            texture_dx: Vec2::zeros(),
+            texture_dy: Vec2::zeros(),
+            world_position: Vec3::zeros(),
+            normal: Vec3::zeros(),
+            tangent: Vec3::zeros(),
+            emission: Color::black(),
+            alpha: 1.0,
         }
     }
 
@@ -43,6 +69,13 @@ impl Fragment {
             depth,
             vertex_position,
             texture_position,
+            texture_dx: Vec2::zeros(),
+            texture_dy: Vec2::zeros(),
+            world_position: Vec3::zeros(),
+            normal: Vec3::zeros(),
+            tangent: Vec3::zeros(),
+            emission: Color::black(),
+            alpha: 1.0,
         }
     }
 
@@ -90,6 +123,7 @@ pub fn wireframe_triangle(color: &Color, v1: &Vertex, v2: &Vertex, v3: &Vertex)
         .collect()
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn triangle(
     v1: &Vertex,
     v2: &Vertex,
@@ -99,6 +133,7 @@ pub fn triangle(
     lights: &[Light],
     custom_depth: Option<f32>,
     wireframe_color: &Option<Color>,
+    scissor: Option<&Rect>,
 ) -> Vec<Fragment> {
     let mut fragments = match wireframe_color {
         Some(color) => wireframe_triangle(&color, v1, v2, v3),
@@ -110,7 +145,12 @@ pub fn triangle(
     let (a, b, c) = (v1.screen_position, v2.screen_position, v3.screen_position);
 
     let triangle_area = edge_function(&a, &b, &vec3_to_vec2(&c));
+    let (texture_dx, texture_dy) = texture_derivatives(v1, v2, v3, triangle_area);
     let (min, max) = calculate_bounding_box(&a, &b, &c);
+    let (min, max) = match scissor {
+        Some(rect) => clip_bounding_box(min, max, rect),
+        None => (min, max),
+    };
 
     let base_color = Color::new(100, 100, 100);
 
@@ -136,6 +176,11 @@ pub fn triangle(
                     w1 * v1.model_position + w2 * v2.model_position + w3 * v3.model_position
                 };
 
+                let tangent = w1 * v1.tangent + w2 * v2.tangent + w3 * v3.tangent;
+
+                let world_position =
+                    w1 * v1.world_position + w2 * v2.world_position + w3 * v3.world_position;
+
                 // Interpolated texture coords...
                 let tex_cords = w1 * v1.tex_coords + w2 * v2.tex_coords + w3 * v3.tex_coords;
 
@@ -163,15 +208,22 @@ pub fn triangle(
 
                 let mut intensity = 0.0;
                 for light_source in lights {
-                    let light_dir = (position - light_source.position).normalize();
-                    intensity += dot(&light_dir, &normal) * light_source.intensity;
+                    let (light_dir, light_intensity) = light_source.illuminate(&world_position);
+                    intensity += dot(&light_dir, &normal).max(0.0) * light_intensity;
                 }
 
                 intensity = intensity.clamp(0.0, 1.0);
 
-                fragments.push(Fragment::new_with_intensity(
-                    point, base_color, depth, position, intensity, tex_cords,
-                ));
+                fragments.push(Fragment {
+                    world_position,
+                    normal,
+                    tangent,
+                    texture_dx,
+                    texture_dy,
+                    ..Fragment::new_with_intensity(
+                        point, base_color, depth, position, intensity, tex_cords,
+                    )
+                });
             }
         })
     });
@@ -189,6 +241,35 @@ pub fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> ((i32, i32), (
     ((minx as i32, miny as i32), (maxx as i32, maxy as i32))
 }
 
+/// Restricts the bounding box so it doesn't go outside the given rectangle.
+fn clip_bounding_box(min: (i32, i32), max: (i32, i32), rect: &Rect) -> ((i32, i32), (i32, i32)) {
+    let rect_min = (rect.x as i32, rect.y as i32);
+    let rect_max = (
+        (rect.x + rect.width) as i32 - 1,
+        (rect.y + rect.height) as i32 - 1,
+    );
+
+    (
+        (min.0.max(rect_min.0), min.1.max(rect_min.1)),
+        (max.0.min(rect_max.0), max.1.min(rect_max.1)),
+    )
+}
+
+/// How much the texture coordinates change along the triangle from one pixel to the next,
+/// on X and on Y. It's the same for every pixel since they're interpolated linearly.
+fn texture_derivatives(v1: &Vertex, v2: &Vertex, v3: &Vertex, area: f32) -> (Vec2, Vec2) {
+    let (a, b, c) = (v1.screen_position, v2.screen_position, v3.screen_position);
+
+    // Derivatives of each barycentric coordinate, following `barycentric_coordinates`.
+    let dw1 = vec2(c.y - b.y, b.x - c.x) / area;
+    let dw2 = vec2(a.y - c.y, c.x - a.x) / area;
+    let dw3 = vec2(b.y - a.y, a.x - b.x) / area;
+
+    let dx = v1.tex_coords * dw1.x + v2.tex_coords * dw2.x + v3.tex_coords * dw3.x;
+    let dy = v1.tex_coords * dw1.y + v2.tex_coords * dw2.y + v3.tex_coords * dw3.y;
+    (dx, dy)
+}
+
 fn barycentric_coordinates(p: &Vec2, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) -> (f32, f32, f32) {
     let w1 = edge_function(b, c, p) / area;
     let w2 = edge_function(c, a, p) / area;
@@ -199,3 +280,112 @@ fn barycentric_coordinates(p: &Vec2, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) ->
 fn edge_function(a: &Vec3, b: &Vec3, c: &Vec2) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec3;
+    use rayon::prelude::*;
+
+    use super::*;
+    use crate::{
+        light::{Attenuation, LightKind},
+        obj::{load_objs, Obj},
+        vertex::shader::{
+            create_model_matrix, create_projection_matrix, create_view_matrix,
+            create_viewport_matrix, vertex_shader, Uniforms,
+        },
+    };
+
+    /// Rasterizes the faces of a sphere that look towards the camera.
+    fn rasterize_sphere(objs: &[Obj], center: Vec3, eye: Vec3, light: &Light) -> Vec<Fragment> {
+        let model_matrix = create_model_matrix(center, 1.0, nalgebra_glm::Quat::identity());
+        let up = if (eye - center).normalize().y.abs() > 0.99 {
+            vec3(0.0, 0.0, 1.0)
+        } else {
+            vec3(0.0, 1.0, 0.0)
+        };
+        let uniforms = Uniforms {
+            camera_position: eye,
+            model_matrix,
+            view_matrix: create_view_matrix(eye, center, up),
+            projection_matrix: create_projection_matrix(64.0, 64.0, 0.5),
+            viewport_matrix: create_viewport_matrix(64.0, 64.0),
+            time: 0.0,
+        };
+        let camera_direction = (center - eye).normalize();
+
+        let vertices: Vec<Vertex> = objs
+            .par_iter()
+            .flatten()
+            .map(|v| vertex_shader(v, &uniforms))
+            .collect();
+
+        vertices
+            .par_chunks(3)
+            .flat_map(|tri| {
+                triangle(
+                    &tri[0],
+                    &tri[1],
+                    &tri[2],
+                    Some(&camera_direction),
+                    &false,
+                    std::slice::from_ref(light),
+                    None,
+                    &None,
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_orbiting_sphere_lit_from_known_directions() {
+        // Far away from the origin, where the planets orbit.
+        let center = vec3(20.0, 0.0, -10.0);
+        let objs = load_objs("assets/models/sphere.obj").unwrap();
+
+        for direction in [vec3(-1.0, 0.0, 0.0), vec3(0.0, 0.6, -0.8)] {
+            let light = Light {
+                kind: LightKind::Point {
+                    attenuation: Attenuation::NONE,
+                },
+                position: center + direction * 50.0,
+                color: Color::white(),
+                intensity: 1.0,
+                radius: 0.0,
+                casts_shadows: false,
+            };
+
+            let day_side = rasterize_sphere(&objs, center, center + direction * 10.0, &light);
+            let average = day_side.iter().map(|f| f.intensity).sum::<f32>() / day_side.len() as f32;
+            assert!(!day_side.is_empty());
+            assert!(average > 0.6, "{direction:?} day side average {average}");
+
+            let night_side = rasterize_sphere(&objs, center, center - direction * 10.0, &light);
+            let brightest = night_side.iter().map(|f| f.intensity).fold(0.0, f32::max);
+            assert!(!night_side.is_empty());
+            assert!(brightest < 0.1, "{direction:?} night side max {brightest}");
+        }
+    }
+
+    #[test]
+    fn test_texture_derivatives_follow_the_screen() {
+        // Every pixel moves the texture coordinates 0.02, with `v` going down on Y.
+        let vertex = |x: f32, y: f32, u: f32, v: f32| Vertex {
+            screen_position: vec3(x, y, 0.0),
+            ..Vertex::new(vec3(x, y, 0.0), Vec3::z(), vec2(u, v))
+        };
+        let v1 = vertex(0.0, 0.0, 0.0, 1.0);
+        let v2 = vertex(10.0, 0.0, 0.2, 1.0);
+        let v3 = vertex(0.0, 10.0, 0.0, 0.8);
+        let area = edge_function(
+            &v1.screen_position,
+            &v2.screen_position,
+            &vec3_to_vec2(&v3.screen_position),
+        );
+
+        let (dx, dy) = texture_derivatives(&v1, &v2, &v3, area);
+        assert!((dx - vec2(0.02, 0.0)).norm() < 1e-6);
+        assert!((dy - vec2(0.0, -0.02)).norm() < 1e-6);
+    }
+}
@@ -0,0 +1,259 @@
+//! Precomputes the shader layers that don't change over time into textures,
+//! so they don't get recomputed for every fragment of every frame.
+
+use fastnoise_lite::FastNoiseLite;
+use nalgebra_glm::{vec3, Mat4, Vec3};
+
+use crate::{
+    color::{blenders::BlendMode, Color},
+    obj::Obj,
+    texture::{sampler::Sampler, GameTextures, Texture, Textures},
+    vertex::{shader::ShaderType, shader::Uniforms, Vertex},
+    viewport::Rect,
+    Entity, EntityShader,
+};
+
+use super::{
+    lighting::LightingContext,
+    material::Material,
+    shaders::{fragment_shader, ShaderContext, ShaderRegistry},
+    triangle,
+};
+
+/// How many times the colors get spread into the texels that no triangle covers.
+const BAKE_PADDING: usize = 2;
+
+/// How big a baked texture is and where to save it.
+#[derive(Debug, Clone)]
+pub struct BakeSettings {
+    pub width: u32,
+    pub height: u32,
+    /// Also writes the baked texture into this image file.
+    pub output_path: Option<String>,
+}
+
+/// Whether the color of the layer only depends on the position and texture coordinates of the fragment.
+pub fn is_bakeable(shader_type: &ShaderType) -> bool {
+    match shader_type {
+        ShaderType::BaseColor
+        | ShaderType::Stripe { .. }
+        | ShaderType::GlowShader { .. }
+        | ShaderType::Texture { .. } => true,
+        ShaderType::FBmShader { speed, .. } | ShaderType::CellularShader { speed, .. } => {
+            *speed == 0.0
+        }
+        _ => false,
+    }
+}
+
+/// How many layers, counting from the first one, can be baked.
+pub fn bakeable_layers(shaders: &[EntityShader]) -> usize {
+    shaders
+        .iter()
+        .take_while(|(shader_type, _, _)| is_bakeable(shader_type))
+        .count()
+}
+
+/// Evaluates the layers over the texture coordinates of the meshes.
+/// Texels outside of every triangle get the color of their neighbors, so seams don't show.
+pub fn bake_layers(
+    objs: &[Obj],
+    layers: &[EntityShader],
+    width: u32,
+    height: u32,
+    registry: &ShaderRegistry,
+    context: &mut ShaderContext,
+) -> Texture {
+    let (w, h) = (width as usize, height as usize);
+    let mut colors: Vec<Option<Color>> = vec![None; w * h];
+    let scissor = Rect::new(0, 0, w, h);
+
+    // Rasterizes the triangles on the texture, the screen position is where its UVs land.
+    let to_texture_space = |vertex: &Vertex| Vertex {
+        screen_position: vec3(
+            vertex.tex_coords.x * width as f32,
+            vertex.tex_coords.y * height as f32,
+            0.0,
+        ),
+        ..vertex.clone()
+    };
+
+    for obj in objs {
+        for tri in obj.chunks_exact(3) {
+            let [v1, v2, v3] = [&tri[0], &tri[1], &tri[2]].map(to_texture_space);
+            let fragments = triangle(
+                &v1,
+                &v2,
+                &v3,
+                None,
+                &false,
+                &[],
+                None,
+                &None,
+                Some(&scissor),
+            );
+
+            for fragment in fragments {
+                let (x, y) = (fragment.position.x as usize, fragment.position.y as usize);
+                let fragment = fragment_shader(fragment, layers, registry, context);
+                colors[y * w + x] = Some(fragment.color);
+            }
+        }
+    }
+
+    for _ in 0..BAKE_PADDING {
+        colors = pad(&colors, w, h);
+    }
+
+    let colors = colors
+        .into_iter()
+        .map(|color| color.unwrap_or(Color::black()))
+        .collect();
+    Texture::from_colors(width, height, colors)
+}
+
+/// Fills every empty texel next to a painted one with the color of that neighbor.
+fn pad(colors: &[Option<Color>], width: usize, height: usize) -> Vec<Option<Color>> {
+    let mut padded = colors.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            if colors[y * width + x].is_some() {
+                continue;
+            }
+
+            let neighbor = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .into_iter()
+                .filter_map(|(dx, dy)| {
+                    let nx = x.checked_add_signed(dx).filter(|nx| *nx < width)?;
+                    let ny = y.checked_add_signed(dy).filter(|ny| *ny < height)?;
+                    colors[ny * width + nx]
+                })
+                .next();
+            padded[y * width + x] = neighbor;
+        }
+    }
+
+    padded
+}
+
+/// The baked layers don't depend on the camera nor the time.
+fn bake_uniforms() -> Uniforms {
+    Uniforms {
+        camera_position: Vec3::zeros(),
+        model_matrix: Mat4::identity(),
+        view_matrix: Mat4::identity(),
+        projection_matrix: Mat4::identity(),
+        viewport_matrix: Mat4::identity(),
+        time: 0.0,
+    }
+}
+
+/// Bakes the first layers of the entity that can be baked and
+/// replaces them with a `ShaderType::Texture` layer.
+/// Returns the id of the baked texture, or `None` if no layer could be baked.
+///
+/// * `noise`: Must be the same noise used when rendering, or the bake won't match.
+pub fn bake_entity(
+    entity: &mut Entity,
+    settings: &BakeSettings,
+    registry: &ShaderRegistry,
+    textures: &mut GameTextures,
+    noise: &mut FastNoiseLite,
+) -> image::ImageResult<Option<Textures>> {
+    let layer_count = bakeable_layers(&entity.shaders);
+    if layer_count == 0 {
+        return Ok(None);
+    }
+
+    let uniforms = bake_uniforms();
+    let lighting = LightingContext {
+        ambient: 0.0,
+        lights: &[],
+        shadow_maps: &[],
+        occluders: &[],
+    };
+    let mut context = ShaderContext {
+        uniforms: &uniforms,
+        textures: &*textures,
+        lighting: &lighting,
+        // The emission is added after the layers, it shouldn't end up inside the texture.
+        material: &Material::default(),
+        noise,
+    };
+
+    let texture = bake_layers(
+        &entity.objs,
+        &entity.shaders[..layer_count],
+        settings.width,
+        settings.height,
+        registry,
+        &mut context,
+    );
+    if let Some(output_path) = &settings.output_path {
+        texture.save(output_path)?;
+    }
+
+    let texture = textures.add_baked(texture);
+    entity.shaders.splice(
+        ..layer_count,
+        [(
+            ShaderType::Texture {
+                texture,
+                sampler: Sampler::default(),
+            },
+            vec![],
+            BlendMode::Replace,
+        )],
+    );
+
+    Ok(Some(texture))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{obj::create_ring, vertex::shader::create_noise};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_bake_matches_the_layers() {
+        // Standing up, so the stripes go around the ring.
+        let ring = create_ring(1.0, 2.0, FRAC_PI_2, 16);
+        let layers = vec![
+            (
+                ShaderType::Stripe { stripe_width: 0.5 },
+                vec![Color::new(255, 0, 0), Color::new(0, 0, 255)],
+                BlendMode::Replace,
+            ),
+            (ShaderType::Lighting, vec![], BlendMode::Replace),
+        ];
+        assert_eq!(bakeable_layers(&layers), 1);
+
+        let registry = ShaderRegistry::with_builtins();
+        let textures = GameTextures::new("assets/textures/");
+        let mut noise = create_noise();
+        let uniforms = bake_uniforms();
+        let lighting = LightingContext {
+            ambient: 0.0,
+            lights: &[],
+            shadow_maps: &[],
+            occluders: &[],
+        };
+        let mut context = ShaderContext {
+            uniforms: &uniforms,
+            textures: &textures,
+            lighting: &lighting,
+            material: &Material::default(),
+            noise: &mut noise,
+        };
+
+        let texture = bake_layers(&[ring], &layers[..1], 32, 32, &registry, &mut context);
+
+        // The ring covers the whole texture, and `v` goes around it.
+        let colors: Vec<Color> = (0..32)
+            .map(|y| texture.get_pixel_color(0.5, (y as f32 + 0.5) / 32.0))
+            .collect();
+        assert!(colors.contains(&Color::new(255, 0, 0)));
+        assert!(colors.contains(&Color::new(0, 0, 255)));
+    }
+}
@@ -1,8 +1,10 @@
 use nalgebra_glm::{vec3, Vec3};
+use serde::{Deserialize, Serialize};
 
 /// Contains all the parameters to make an ellipsis.
 /// The formula for an ellipsis centered at (h,k) is:
 /// (x-h)^2 / a^2 + (z-k)^2 / b^2 = 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ellipsis {
     pub center: Vec3,
     pub a: f32,
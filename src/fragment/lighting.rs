@@ -0,0 +1,196 @@
+use nalgebra_glm::{angle, smoothstep, Vec3};
+
+use crate::{
+    color::Color,
+    light::{AmbientLightIntensity, Light},
+    picking::BoundingSphere,
+    shadow::ShadowMap,
+};
+
+use super::material::Material;
+
+/// Everything the lighting stage needs to know about the scene.
+#[derive(Clone, Copy)]
+pub struct LightingContext<'a> {
+    /// Fraction of the surface color that's visible even without any light,
+    /// like on the night side of a planet.
+    pub ambient: AmbientLightIntensity,
+    pub lights: &'a [Light],
+    /// The shadow map of each light, in the same order as `lights`.
+    pub shadow_maps: &'a [Option<ShadowMap>],
+    /// Bounding spheres of the entities that can cast a shadow on the fragments being lit.
+    pub occluders: &'a [BoundingSphere],
+}
+
+/// Computes the color of a surface using the Blinn-Phong reflection model.
+///
+/// * `base_color`: Color of the surface before being lit.
+/// * `position`: Position of the surface in world space.
+/// * `normal`: Normal of the surface in world space.
+/// * `camera_position`: Where the surface is being seen from.
+/// * `lighting`: The lights and shadow casters of the scene.
+/// * `material`: How the surface reacts to light.
+pub fn blinn_phong(
+    base_color: &Color,
+    position: &Vec3,
+    normal: &Vec3,
+    camera_position: &Vec3,
+    lighting: &LightingContext,
+    material: &Material,
+) -> Color {
+    let normal = normal.normalize();
+    let view_dir = (camera_position - position).normalize();
+    let (diffuse_albedo, specular_albedo) = material.albedo;
+
+    lighting
+        .lights
+        .iter()
+        .enumerate()
+        .fold(*base_color * lighting.ambient, |color, (i, light)| {
+            let (light_dir, intensity) = light.illuminate(position);
+            let diffuse = normal.dot(&light_dir);
+
+            // The light is behind the surface or doesn't reach it...
+            if diffuse <= 0.0 || intensity <= 0.0 {
+                return color;
+            }
+
+            // Both shadows cover the same bodies, so only the darkest one is used.
+            let mut visibility = shadow_factor(position, light, lighting.occluders);
+            if let Some(Some(shadow_map)) = lighting.shadow_maps.get(i) {
+                visibility = visibility.min(shadow_map.visibility(position));
+            }
+
+            let intensity = intensity * visibility;
+            if intensity <= 0.0 {
+                return color;
+            }
+
+            let halfway = (light_dir + view_dir).normalize();
+            let specular = normal.dot(&halfway).max(0.0).powf(material.specular);
+            let tinted_color = base_color.blend_multiply(&light.color);
+
+            color
+                + tinted_color * (diffuse * diffuse_albedo * intensity)
+                + light.color * (specular * specular_albedo * intensity)
+        })
+}
+
+/// Computes how much of the light reaches the point without being blocked by an occluder.
+/// Zero means the point is in full shadow (umbra) and one means it isn't shadowed at all.
+///
+/// The light and the occluders are treated as discs on the sky of the point,
+/// so the penumbra gets wider the bigger the light looks from the point.
+pub fn shadow_factor(position: &Vec3, light: &Light, occluders: &[BoundingSphere]) -> f32 {
+    let (light_dir, _) = light.illuminate(position);
+    let light_distance = light.distance_to(position);
+    let light_size = (light.radius / light_distance).clamp(0.0, 1.0).asin();
+
+    occluders
+        .iter()
+        .filter(|occluder| {
+            // The light is inside the occluder, like the sun inside its own sphere.
+            (occluder.center - light.position).magnitude() >= occluder.radius
+                || light_distance.is_infinite()
+        })
+        .map(|occluder| {
+            let to_occluder = occluder.center - position;
+            let occluder_distance = to_occluder.magnitude();
+
+            // The point is inside the occluder or the occluder is behind the light...
+            if occluder_distance <= occluder.radius || occluder_distance >= light_distance {
+                return 1.0;
+            }
+
+            let occluder_size = (occluder.radius / occluder_distance).asin();
+            let separation = angle(&light_dir, &to_occluder);
+
+            if light_size <= f32::EPSILON {
+                return if separation < occluder_size { 0.0 } else { 1.0 };
+            }
+
+            smoothstep(
+                (occluder_size - light_size).abs(),
+                occluder_size + light_size,
+                separation,
+            )
+        })
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec3;
+
+    use super::*;
+    use crate::{
+        equal,
+        fragment::material::WATER,
+        light::{Attenuation, LightKind},
+    };
+
+    fn white_light(position: Vec3, radius: f32) -> Light {
+        Light {
+            kind: LightKind::Point {
+                attenuation: Attenuation::NONE,
+            },
+            position,
+            color: Color::white(),
+            intensity: 1.0,
+            radius,
+            casts_shadows: false,
+        }
+    }
+
+    #[test]
+    fn test_highlight_faces_the_light() {
+        let lights = [white_light(vec3(0.0, 0.0, 10.0), 0.0)];
+        let lighting = LightingContext {
+            ambient: 0.1,
+            lights: &lights,
+            shadow_maps: &[],
+            occluders: &[],
+        };
+        let base_color = Color::new(0, 0, 200);
+        let camera_position = vec3(0.0, 0.0, 10.0);
+        let shade = |normal: Vec3| {
+            blinn_phong(
+                &base_color,
+                &normal,
+                &normal,
+                &camera_position,
+                &lighting,
+                &WATER,
+            )
+        };
+
+        let facing = shade(vec3(0.0, 0.0, 1.0));
+        let grazing = shade(vec3(1.0, 0.0, 0.0));
+        let behind = shade(vec3(0.0, 0.0, -1.0));
+
+        // The specular highlight adds white on top of the surface color.
+        assert!(facing.r > 0 && facing.b > grazing.b);
+        assert_eq!(grazing, base_color * lighting.ambient);
+        assert_eq!(behind, base_color * lighting.ambient);
+    }
+
+    #[test]
+    fn test_eclipse_has_umbra_and_penumbra() {
+        // A sun at the origin, a moon in between and a planet behind it.
+        let sun = white_light(Vec3::zeros(), 4.0);
+        let moon = [BoundingSphere {
+            center: vec3(20.0, 0.0, 0.0),
+            radius: 1.0,
+        }];
+
+        let umbra = shadow_factor(&vec3(22.0, 0.0, 0.0), &sun, &moon);
+        let penumbra = shadow_factor(&vec3(22.0, 1.2, 0.0), &sun, &moon);
+        let lit = shadow_factor(&vec3(22.0, 5.0, 0.0), &sun, &moon);
+        let in_front = shadow_factor(&vec3(18.0, 0.0, 0.0), &sun, &moon);
+
+        assert!(equal(umbra, 0.0, 1e-5));
+        assert!(penumbra > 0.0 && penumbra < 1.0);
+        assert!(equal(lit, 1.0, 1e-5));
+        assert!(equal(in_front, 1.0, 1e-5));
+    }
+}
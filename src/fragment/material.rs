@@ -1,8 +1,11 @@
+use serde::{Deserialize, Serialize};
+
 use crate::color::Color;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Material {
     pub diffuse: Color,
+    /// El exponente especular, entre más grande más pequeño es el brillo.
     pub specular: f32,
     /// La cantidad de luz que un material absorbe, en porcentaje (0,1).
     /// y también
@@ -14,9 +17,18 @@ pub struct Material {
     pub transparency: f32,
     /// El índice refractivo del material.
     pub refractive_index: f32,
+    /// El color de la luz que el material emite por sí mismo, no depende de la iluminación.
+    pub emissive: Color,
+    /// Qué tan fuerte es la luz emitida, cero significa que no emite luz.
+    pub emissive_strength: f32,
 }
 
 impl Material {
+    /// Computes the light emitted by the material.
+    pub fn emission(&self) -> Color {
+        self.emissive * self.emissive_strength
+    }
+
     pub const fn default() -> Self {
         Material {
             diffuse: Color::default(),
@@ -25,6 +37,8 @@ impl Material {
             reflectivity: 0.0,
             transparency: 0.0,
             refractive_index: 0.0,
+            emissive: Color::black(),
+            emissive_strength: 0.0,
         }
     }
 }
@@ -36,6 +50,8 @@ pub const DIRT: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.42,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const STONE: Material = Material {
@@ -45,6 +61,8 @@ pub const STONE: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.42,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const WATER: Material = Material {
@@ -54,6 +72,8 @@ pub const WATER: Material = Material {
     reflectivity: 0.1,
     transparency: 0.9,
     refractive_index: 1.25,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const OBSIDIAN: Material = Material {
@@ -63,6 +83,8 @@ pub const OBSIDIAN: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.25,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const PORTAL: Material = Material {
@@ -72,6 +94,8 @@ pub const PORTAL: Material = Material {
     reflectivity: 0.0,
     transparency: 0.4,
     refractive_index: 1.2,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const NETHERRACK: Material = Material {
@@ -81,6 +105,8 @@ pub const NETHERRACK: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.2,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const MAGMA: Material = Material {
@@ -90,6 +116,8 @@ pub const MAGMA: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.2,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const GOLD: Material = Material {
@@ -99,6 +127,8 @@ pub const GOLD: Material = Material {
     reflectivity: 0.1,
     transparency: 0.0,
     refractive_index: 1.2,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
 };
 
 pub const RUBBER: Material = Material {
@@ -108,4 +138,30 @@ pub const RUBBER: Material = Material {
     reflectivity: 0.0,
     transparency: 0.0,
     refractive_index: 1.51,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
+};
+
+pub const METAL: Material = Material {
+    diffuse: Color::pink(),
+    specular: 60.0,
+    albedo: (0.6, 0.8),
+    reflectivity: 0.3,
+    transparency: 0.0,
+    refractive_index: 2.5,
+    emissive: Color::black(),
+    emissive_strength: 0.0,
+};
+
+pub const CLOUD: Material = Material {
+    diffuse: Color::white(),
+    specular: 1.0,
+    albedo: (0.9, 0.0),
+    ..Material::default()
+};
+
+pub const SUN: Material = Material {
+    emissive: Color::new(255, 170, 50),
+    emissive_strength: 0.8,
+    ..Material::default()
 };
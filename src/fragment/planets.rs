@@ -1,22 +1,33 @@
-use nalgebra_glm::{Mat4, Vec3};
+use nalgebra_glm::{Mat4, Quat, Vec3};
 
 use crate::{
-    color::{blenders::BlendMode, Color},
-    obj::load_objs,
-    vertex::shader::{create_model_matrix, CellularConfig, FractalConfig, ShaderType},
-    Entity, EntityModel, EntityOptimizations,
+    color::{
+        blenders::BlendMode,
+        ramp::{ColorRamp, ColorStop, RampInterpolation},
+        Color,
+    },
+    obj::{load_objs, MeshSource},
+    texture::Textures,
+    vertex::shader::{
+        create_model_matrix, CellularConfig, DomainWarpConfig, FractalConfig, ShaderType,
+        NOISE_SEED,
+    },
+    Entity, EntityModel, EntityOptimizations, ParentLink,
 };
 
-use super::ellipsis::Ellipsis;
+use super::{
+    ellipsis::Ellipsis,
+    material::{CLOUD, DIRT, OBSIDIAN, RUBBER, STONE, SUN, WATER},
+};
 
 pub fn create_default_planet_model_matrix() -> Mat4 {
     let model = create_default_planet_model();
-    create_model_matrix(model.translation, model.scale, model.rotation)
+    create_model_matrix(model.translation, model.scale, model.orientation)
 }
 
 pub fn create_default_planet_model() -> EntityModel {
     EntityModel {
-        rotation: Vec3::zeros(),
+        orientation: Quat::identity(),
         translation: Vec3::zeros(),
         scale: 1.0,
     }
@@ -28,6 +39,16 @@ const OPTIMIZATIONS: EntityOptimizations = EntityOptimizations {
     frustum_cutting: true,
 };
 
+// Every entity samples its own region of the noise, so the ones sharing a config don't look alike.
+const GREEN_NOISE_OFFSET: Vec3 = Vec3::new(1000.0, 0.0, 0.0);
+const SNOW_NOISE_OFFSET: Vec3 = Vec3::new(2000.0, 0.0, 0.0);
+const OCEAN_NOISE_OFFSET: Vec3 = Vec3::new(3000.0, 0.0, 0.0);
+const FACE_NOISE_OFFSET: Vec3 = Vec3::new(4000.0, 0.0, 0.0);
+const SUN_NOISE_OFFSET: Vec3 = Vec3::new(5000.0, 0.0, 0.0);
+const GAS_GIANT_NOISE_OFFSET: Vec3 = Vec3::new(6000.0, 0.0, 0.0);
+pub const GREEN_CLOUDS_NOISE_OFFSET: Vec3 = Vec3::new(1500.0, 0.0, 0.0);
+pub const OCEAN_CLOUDS_NOISE_OFFSET: Vec3 = Vec3::new(3500.0, 0.0, 0.0);
+
 // const BASE_SLOWDOWN: f32 = 1e-2;
 const BASE_SLOWDOWN: f32 = 1e-4;
 
@@ -50,7 +71,7 @@ pub fn create_disco_planet() -> Entity {
             vec![Color::black(), Color::blue()],
             BlendMode::Normal,
         ),
-        (ShaderType::Intensity, vec![], BlendMode::Replace),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -62,11 +83,17 @@ pub fn create_disco_planet() -> Entity {
     });
 
     Entity {
+        name: Some("disco_planet".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: OBSIDIAN,
         wireframe_color: None,
         ellipsis,
         custom_depth: None,
         model: create_default_planet_model(),
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         use_screen_position: false,
         model_matrix: create_default_planet_model_matrix(),
         shaders,
@@ -94,13 +121,28 @@ pub fn create_ocean_planet() -> Entity {
                     lacunarity: 2.0,
                     gain: 0.8,
                     weighted_strength: 0.0,
+                    spherical: true,
+                    offset: OCEAN_NOISE_OFFSET,
                     f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
+                ramp: None,
             },
             vec![Color::new(230, 230, 230)],
             BlendMode::Screen,
         ),
-        (ShaderType::Intensity, vec![], BlendMode::Replace),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
+        (
+            ShaderType::Atmosphere {
+                density: 1.2,
+                falloff: 3.0,
+            },
+            vec![0x5fb4ff.into()],
+            BlendMode::Add,
+        ),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -112,12 +154,18 @@ pub fn create_ocean_planet() -> Entity {
     });
 
     Entity {
+        name: Some("ocean_planet".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: WATER,
         wireframe_color: None,
         ellipsis,
         custom_depth: None,
         model: create_default_planet_model(),
         use_screen_position: false,
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         model_matrix: create_default_planet_model_matrix(),
         shaders,
         optimizations: OPTIMIZATIONS,
@@ -128,11 +176,43 @@ pub fn create_gas_giant() -> Entity {
     let planet_obj = load_objs(SPHERE_OBJ).unwrap();
     let shaders = vec![
         (
-            ShaderType::BaseColor,
-            vec![0xc2e9ed.into()],
+            ShaderType::Stripe { stripe_width: 0.12 },
+            vec![
+                0xc2e9ed.into(),
+                0x9fc9d6.into(),
+                0xdcefe6.into(),
+                0x7fb0c4.into(),
+            ],
             BlendMode::Replace,
         ),
-        (ShaderType::Intensity, vec![], BlendMode::Replace),
+        // Warped ridges swirl the edges of the bands.
+        (
+            ShaderType::FBmShader {
+                zoom: 150.0,
+                speed: 1e-2,
+                fractal: FractalConfig {
+                    octaves: 3,
+                    lacunarity: 2.0,
+                    gain: 0.5,
+                    weighted_strength: 0.0,
+                    f_type: fastnoise_lite::FractalType::Ridged,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: Some(DomainWarpConfig {
+                        warp_type: fastnoise_lite::DomainWarpType::OpenSimplex2,
+                        amplitude: 40.0,
+                        f_type: fastnoise_lite::FractalType::DomainWarpProgressive,
+                    }),
+                    spherical: true,
+                    offset: GAS_GIANT_NOISE_OFFSET,
+                },
+                ramp: None,
+            },
+            vec![0xe8f4f6.into()],
+            BlendMode::Overlay,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -144,18 +224,140 @@ pub fn create_gas_giant() -> Entity {
     });
 
     Entity {
+        name: Some("gas_giant".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: RUBBER,
         ellipsis,
         wireframe_color: None,
         custom_depth: None,
         model: create_default_planet_model(),
         use_screen_position: false,
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         model_matrix: create_default_planet_model_matrix(),
         shaders,
         optimizations: OPTIMIZATIONS,
     }
 }
 
+/// Saturn-like rings for the gas giant.
+///
+/// * `parent_index`: Index of the gas giant on `Model::entities`.
+pub fn create_gas_giant_rings(parent_index: usize) -> Entity {
+    let mesh = MeshSource::Ring {
+        inner_radius: 1.4,
+        outer_radius: 2.4,
+        tilt: 0.45,
+        segments: 96,
+    };
+    let shaders = vec![
+        (
+            ShaderType::RingBands {
+                bands: 7.0,
+                gap: 0.2,
+            },
+            vec![0xd8c6a0.into(), 0xa89070.into(), 0xeee2c8.into()],
+            BlendMode::Replace,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
+    ];
+
+    Entity {
+        name: Some("gas_giant_rings".to_string()),
+        lights: vec![],
+        parent: Some(ParentLink {
+            index: parent_index,
+            scale: 1.0,
+            spin: 0.0,
+        }),
+        transparent: true,
+        material: STONE,
+        ellipsis: None,
+        wireframe_color: None,
+        custom_depth: None,
+        model: create_default_planet_model(),
+        use_screen_position: false,
+        objs: mesh.load().unwrap(),
+        mesh: Some(mesh),
+        model_matrix: create_default_planet_model_matrix(),
+        shaders,
+        // Only the face of the ring looking at the camera gets rendered.
+        optimizations: EntityOptimizations {
+            camera_direction: true,
+            frustum_cutting: true,
+        },
+    }
+}
+
+/// A shell of clouds slightly bigger than a planet, spinning on top of it.
+///
+/// * `parent_index`: Index of the planet on `Model::entities`.
+/// * `spin`: Radians per millisecond the clouds spin around the planet.
+/// * `noise_offset`: Moves the noise, so every planet gets different clouds.
+///   The built-in ones use `GREEN_CLOUDS_NOISE_OFFSET` and `OCEAN_CLOUDS_NOISE_OFFSET`.
+pub fn create_cloud_layer(parent_index: usize, spin: f32, noise_offset: Vec3) -> Entity {
+    let mesh = MeshSource::Obj(SPHERE_OBJ.to_string());
+    let shaders = vec![
+        (
+            ShaderType::BaseColor,
+            vec![Color::white()],
+            BlendMode::Replace,
+        ),
+        (
+            ShaderType::NoiseAlpha {
+                zoom: 300.0,
+                speed: 0.02,
+                fractal: FractalConfig {
+                    octaves: 5,
+                    lacunarity: 2.0,
+                    gain: 0.5,
+                    weighted_strength: 0.0,
+                    f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
+                    spherical: true,
+                    offset: noise_offset,
+                },
+                threshold: 0.45,
+                softness: 0.2,
+            },
+            vec![],
+            BlendMode::Replace,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
+    ];
+
+    Entity {
+        name: Some("clouds".to_string()),
+        lights: vec![],
+        parent: Some(ParentLink {
+            index: parent_index,
+            scale: 1.04,
+            spin,
+        }),
+        transparent: true,
+        material: CLOUD,
+        ellipsis: None,
+        wireframe_color: None,
+        custom_depth: None,
+        model: create_default_planet_model(),
+        use_screen_position: false,
+        objs: mesh.load().unwrap(),
+        mesh: Some(mesh),
+        model_matrix: create_default_planet_model_matrix(),
+        shaders,
+        // The clouds on the back of the planet would be blended on top of the front ones.
+        optimizations: EntityOptimizations {
+            camera_direction: true,
+            frustum_cutting: true,
+        },
+    }
+}
+
 pub fn create_face_planet() -> Entity {
     let planet_obj = load_objs(SPHERE_OBJ).unwrap();
     let shaders = vec![
@@ -168,13 +370,20 @@ pub fn create_face_planet() -> Entity {
                     lacunarity: 2.0,
                     gain: 1.26,
                     weighted_strength: 0.0,
+                    spherical: true,
+                    offset: FACE_NOISE_OFFSET,
                     f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
                 cellular: CellularConfig {
                     distance_func: fastnoise_lite::CellularDistanceFunction::EuclideanSq,
                     return_type: fastnoise_lite::CellularReturnType::Distance2Div,
                     jitter: 1.0,
                 },
+                ramp: None,
             },
             vec![Color::red()],
             BlendMode::Replace,
@@ -184,7 +393,15 @@ pub fn create_face_planet() -> Entity {
             vec![0xff7900.into()],
             BlendMode::Overlay,
         ),
-        // (ShaderType::Intensity, vec![], BlendMode::Replace),
+        (
+            ShaderType::NormalMap {
+                texture: Textures::CratersNormal,
+                strength: 0.6,
+            },
+            vec![],
+            BlendMode::Replace,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -196,12 +413,18 @@ pub fn create_face_planet() -> Entity {
     });
 
     Entity {
+        name: Some("face_planet".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: STONE,
         wireframe_color: None,
         ellipsis,
         custom_depth: None,
         model: create_default_planet_model(),
         use_screen_position: false,
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         model_matrix: create_default_planet_model_matrix(),
         shaders,
         optimizations: OPTIMIZATIONS,
@@ -220,13 +443,41 @@ pub fn create_snow_planet() -> Entity {
                     lacunarity: 0.5,
                     gain: 1.0,
                     weighted_strength: 0.0,
+                    spherical: true,
+                    offset: SNOW_NOISE_OFFSET,
                     f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
+                ramp: None,
             },
             vec![0xc2e9ed.into()],
             BlendMode::Add,
         ),
-        (ShaderType::Intensity, vec![], BlendMode::Replace),
+        (
+            ShaderType::NoiseBump {
+                zoom: 500.0,
+                strength: 0.01,
+                fractal: FractalConfig {
+                    octaves: 3,
+                    lacunarity: 0.5,
+                    gain: 1.0,
+                    weighted_strength: 0.0,
+                    spherical: true,
+                    offset: SNOW_NOISE_OFFSET,
+                    f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
+                },
+            },
+            vec![],
+            BlendMode::Replace,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -238,12 +489,18 @@ pub fn create_snow_planet() -> Entity {
     });
 
     Entity {
+        name: Some("snow_planet".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: STONE,
         wireframe_color: None,
         ellipsis,
         custom_depth: None,
         model: create_default_planet_model(),
         use_screen_position: false,
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         model_matrix: create_default_planet_model_matrix(),
         shaders,
         optimizations: OPTIMIZATIONS,
@@ -267,18 +524,25 @@ pub fn create_sun(starting_position: Vec3) -> Entity {
                     lacunarity: 0.5,
                     gain: 1.0,
                     weighted_strength: 0.0,
+                    spherical: true,
+                    offset: SUN_NOISE_OFFSET,
                     f_type: fastnoise_lite::FractalType::PingPong,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
                 cellular: CellularConfig {
                     distance_func: fastnoise_lite::CellularDistanceFunction::EuclideanSq,
                     return_type: fastnoise_lite::CellularReturnType::Distance,
                     jitter: 1.0,
                 },
+                ramp: None,
             },
             vec![0xc2e9ed.into()],
             BlendMode::Add,
         ),
-        // (ShaderType::Intensity, vec![], BlendMode::Replace),
+        // (ShaderType::Lighting, vec![], BlendMode::Replace),
     ];
 
     let mut model = create_default_planet_model();
@@ -286,13 +550,19 @@ pub fn create_sun(starting_position: Vec3) -> Entity {
     model.scale *= 4.0;
 
     Entity {
+        name: Some("sun".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: SUN,
         wireframe_color: None,
         ellipsis: None,
         custom_depth: None,
-        model_matrix: create_model_matrix(model.translation, model.scale, model.rotation),
+        model_matrix: create_model_matrix(model.translation, model.scale, model.orientation),
         model,
         use_screen_position: false,
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         shaders,
         optimizations: OPTIMIZATIONS,
     }
@@ -301,43 +571,88 @@ pub fn create_sun(starting_position: Vec3) -> Entity {
 pub fn create_green_planet() -> Entity {
     let planet_obj = load_objs(SPHERE_OBJ).unwrap();
     let shaders = vec![
-        (
-            ShaderType::BaseColor,
-            // vec![Color::new(100, 100, 100)],
-            vec![Color::blue()],
-            BlendMode::Replace,
-        ),
         (
             ShaderType::FBmShader {
                 zoom: 200.0,
-                speed: 0.1,
+                speed: 0.0,
                 fractal: FractalConfig {
                     octaves: 4,
                     lacunarity: 2.0,
                     gain: 0.5,
                     weighted_strength: 0.0,
-                    f_type: fastnoise_lite::FractalType::None,
+                    spherical: true,
+                    offset: GREEN_NOISE_OFFSET,
+                    f_type: fastnoise_lite::FractalType::FBm,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
+                // From the bottom of the sea to the peaks of the mountains.
+                ramp: Some(ColorRamp {
+                    stops: vec![
+                        ColorStop {
+                            position: 0.0,
+                            color: 0x0b1d5c.into(),
+                        },
+                        ColorStop {
+                            position: 0.42,
+                            color: 0x1f5fa8.into(),
+                        },
+                        ColorStop {
+                            position: 0.48,
+                            color: 0xd8c98a.into(),
+                        },
+                        ColorStop {
+                            position: 0.53,
+                            color: 0x087227.into(),
+                        },
+                        ColorStop {
+                            position: 0.7,
+                            color: 0x5b4a3a.into(),
+                        },
+                        ColorStop {
+                            position: 0.85,
+                            color: Color::white(),
+                        },
+                    ],
+                    interpolation: RampInterpolation::Smoothstep,
+                }),
             },
-            vec![0x087227.into()],
-            BlendMode::Difference,
+            vec![],
+            BlendMode::Replace,
         ),
         (
-            ShaderType::FBmShader {
+            ShaderType::NoiseBump {
                 zoom: 200.0,
-                speed: 0.0,
+                strength: 0.02,
+                // Ridged noise raises sharp mountain crests.
                 fractal: FractalConfig {
                     octaves: 4,
                     lacunarity: 2.0,
                     gain: 0.5,
                     weighted_strength: 0.0,
-                    f_type: fastnoise_lite::FractalType::None,
+                    spherical: true,
+                    offset: GREEN_NOISE_OFFSET,
+                    f_type: fastnoise_lite::FractalType::Ridged,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: NOISE_SEED,
+                    billow: false,
+                    warp: None,
                 },
             },
-            vec![Color::new(0, 0, 100)],
-            BlendMode::Subtract,
+            vec![],
+            BlendMode::Replace,
+        ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
+        (
+            ShaderType::Atmosphere {
+                density: 0.8,
+                falloff: 2.5,
+            },
+            vec![0x9be7a0.into()],
+            BlendMode::Add,
         ),
-        // (ShaderType::Intensity, vec![], BlendMode::Replace),
     ];
 
     let ellipsis = Some(Ellipsis {
@@ -349,11 +664,17 @@ pub fn create_green_planet() -> Entity {
     });
 
     Entity {
+        name: Some("green_planet".to_string()),
+        lights: vec![],
+        parent: None,
+        transparent: false,
+        material: DIRT,
         wireframe_color: None,
         ellipsis,
         custom_depth: None,
         model: create_default_planet_model(),
         objs: planet_obj,
+        mesh: Some(MeshSource::Obj(SPHERE_OBJ.to_string())),
         use_screen_position: false,
         model_matrix: create_default_planet_model_matrix(),
         shaders,
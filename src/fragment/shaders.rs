@@ -1,120 +1,372 @@
-use std::f32::consts::PI;
+pub mod builtin;
+
+use std::{collections::HashMap, f32::consts::PI, sync::Arc};
 
 use fastnoise_lite::FastNoiseLite;
-use nalgebra_glm::vec2;
+use nalgebra_glm::{smoothstep, Vec3};
 
 use crate::{
-    clamp_with_universe,
     color::Color,
-    texture::{GameTextures, Textures},
-    vertex::shader::{CellularConfig, FractalConfig, ShaderType, Uniforms},
+    texture::{sampler::Sampler, GameTextures, Textures},
+    vertex::shader::{create_normal_matrix, FractalConfig, ShaderType, Uniforms, NOISE_SEED},
     EntityShader,
 };
 
-use super::Fragment;
+use self::builtin::{
+    configure_noise, noise_3d, noise_color, Cellular, FBm, MovingStripes, Stripes, TextureMap,
+};
+
+use super::{
+    lighting::{blinn_phong, LightingContext},
+    material::Material,
+    Fragment,
+};
+
+/// Distance between the samples used to compute the slope of the noise on `ShaderType::NoiseBump`.
+const BUMP_EPSILON: f32 = 0.5;
+
+/// Everything a shader can read besides the fragment it's shading.
+pub struct ShaderContext<'a> {
+    pub uniforms: &'a Uniforms,
+    pub textures: &'a GameTextures,
+    pub lighting: &'a LightingContext<'a>,
+    /// The material of the entity being shaded.
+    pub material: &'a Material,
+    /// Shared by all the noise shaders, they configure it before sampling.
+    pub noise: &'a mut FastNoiseLite,
+}
+
+/// A layer of the shader stack of an entity.
+/// Implement it and add it to a `ShaderRegistry` to use it on `ShaderType::Custom` layers.
+pub trait FragmentShader: Send + Sync {
+    /// Computes the color of the layer, it gets blended into the current color afterwards.
+    ///
+    /// * `fragment`: The fragment being shaded.
+    /// * `colors`: The colors of the layer.
+    /// * `current_color`: The color accumulated by the previous layers.
+    /// * `context`: The uniforms, textures and lights of the scene.
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        colors: &[Color],
+        current_color: &Color,
+        context: &mut ShaderContext,
+    ) -> Color;
+}
 
+/// The shaders `ShaderType::Custom` layers can use, by name.
+#[derive(Clone, Default)]
+pub struct ShaderRegistry {
+    shaders: HashMap<String, Arc<dyn FragmentShader>>,
+}
+
+impl ShaderRegistry {
+    /// A registry with an instance of every built-in shader:
+    /// `stripes`, `moving_stripes`, `fbm`, `cellular` and `space_texture`.
+    pub fn with_builtins() -> Self {
+        let fractal = FractalConfig {
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+            weighted_strength: 0.0,
+            f_type: fastnoise_lite::FractalType::FBm,
+            noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+            seed: NOISE_SEED,
+            billow: false,
+            warp: None,
+            spherical: true,
+            offset: Vec3::zeros(),
+        };
+
+        let mut registry = ShaderRegistry::default();
+        registry.register("stripes", Stripes { stripe_width: 0.1 });
+        registry.register(
+            "moving_stripes",
+            MovingStripes {
+                stripe_width: 0.1,
+                speed: 1e-4,
+            },
+        );
+        registry.register(
+            "fbm",
+            FBm {
+                zoom: 200.0,
+                speed: 0.0,
+                fractal,
+            },
+        );
+        registry.register(
+            "cellular",
+            Cellular {
+                zoom: 200.0,
+                speed: 0.0,
+                fractal,
+                cellular: crate::vertex::shader::CellularConfig {
+                    distance_func: fastnoise_lite::CellularDistanceFunction::EuclideanSq,
+                    return_type: fastnoise_lite::CellularReturnType::Distance,
+                    jitter: 1.0,
+                },
+            },
+        );
+        registry.register(
+            "space_texture",
+            TextureMap {
+                texture: Textures::Space,
+                sampler: Sampler::default(),
+            },
+        );
+
+        registry
+    }
+
+    /// Adds a shader, replacing the one that had the same name.
+    pub fn register(&mut self, name: impl Into<String>, shader: impl FragmentShader + 'static) {
+        self.shaders.insert(name.into(), Arc::new(shader));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn FragmentShader> {
+        self.shaders.get(name).map(|shader| shader.as_ref())
+    }
+}
+
+/// Applies the shader layers of an entity to a fragment.
+///
+/// * `fragment`: The fragment coming out of the rasterizer.
+/// * `inputs`: The shader layers of the entity.
+/// * `registry`: The shaders used by `ShaderType::Custom` layers.
+/// * `context`: The uniforms, textures and lights of the scene.
 pub fn fragment_shader(
     fragment: Fragment,
     inputs: &[EntityShader],
-    uniforms: &Uniforms,
-    noise: &mut FastNoiseLite,
-    textures: &GameTextures,
+    registry: &ShaderRegistry,
+    context: &mut ShaderContext,
 ) -> Fragment {
-    let color = inputs.iter().fold(
-        fragment.color,
-        |acc, (shader_type, colors, blend_strategy)| {
-            let color = match shader_type {
-                ShaderType::Stripe { stripe_width } => {
-                    stripes_shader(&fragment, *stripe_width, colors)
-                }
-                ShaderType::MovingStripes {
-                    stripe_width,
-                    speed,
-                } => moving_stripes(&fragment, *stripe_width, *speed, colors, uniforms),
-                ShaderType::Intensity => intensity_shader(&fragment, &acc),
-                ShaderType::BaseColor => colors[0],
-                ShaderType::GlowShader {
-                    stripe_width,
-                    glow_size,
-                    red,
-                    blue,
-                } => glowing_shader(&fragment, *stripe_width, *glow_size, *red, *blue),
-                ShaderType::FBmShader {
-                    zoom,
-                    speed,
-                    fractal,
-                } => fbm_shader(&fragment, uniforms, colors, *speed, *zoom, fractal, noise),
-                ShaderType::CellularShader {
-                    zoom,
-                    speed,
+    let mut fragment = fragment;
+    let mut acc = fragment.color;
+
+    for (shader_type, colors, blend_strategy) in inputs {
+        let ShaderContext {
+            uniforms,
+            textures,
+            lighting,
+            material,
+            ..
+        } = *context;
+
+        let color = match shader_type {
+            // These stages don't produce a color, they only modify the fragment.
+            ShaderType::NormalMap { texture, strength } => {
+                fragment.normal = normal_map_shader(&fragment, textures, *texture, *strength);
+                continue;
+            }
+            ShaderType::NoiseBump {
+                zoom,
+                strength,
+                fractal,
+            } => {
+                fragment.normal = noise_bump_shader(
+                    &fragment,
+                    uniforms,
+                    *zoom,
+                    *strength,
                     fractal,
-                    cellular,
-                } => cellular_shader(
-                    &fragment, uniforms, colors, *speed, *zoom, fractal, cellular, noise,
-                ),
-                ShaderType::Texture { texture } => texture_shader(&fragment, textures, *texture),
-            };
-
-            acc.blend(&color, blend_strategy)
-        },
-    );
-
-    Fragment { color, ..fragment }
+                    context.noise,
+                );
+                continue;
+            }
+
+            ShaderType::NoiseAlpha {
+                zoom,
+                speed,
+                fractal,
+                threshold,
+                softness,
+            } => {
+                let coverage = FBm {
+                    zoom: *zoom,
+                    speed: *speed,
+                    fractal: *fractal,
+                }
+                .value(&fragment, uniforms.time, context.noise);
+                fragment.alpha *= smoothstep(*threshold, threshold + softness, coverage);
+                continue;
+            }
+
+            ShaderType::Stripe { stripe_width } => Stripes {
+                stripe_width: *stripe_width,
+            }
+            .shade(&fragment, colors, &acc, context),
+            ShaderType::MovingStripes {
+                stripe_width,
+                speed,
+            } => MovingStripes {
+                stripe_width: *stripe_width,
+                speed: *speed,
+            }
+            .shade(&fragment, colors, &acc, context),
+            ShaderType::Intensity => intensity_shader(&fragment, &acc),
+            ShaderType::BaseColor => layer_color(colors, 0),
+            ShaderType::GlowShader {
+                stripe_width,
+                glow_size,
+                red,
+                blue,
+            } => glowing_shader(&fragment, *stripe_width, *glow_size, *red, *blue),
+            ShaderType::FBmShader {
+                zoom,
+                speed,
+                fractal,
+                ramp,
+            } => {
+                let sample = FBm {
+                    zoom: *zoom,
+                    speed: *speed,
+                    fractal: *fractal,
+                }
+                .sample(&fragment, uniforms.time, context.noise);
+                noise_color(sample, colors, ramp.as_ref())
+            }
+            ShaderType::CellularShader {
+                zoom,
+                speed,
+                fractal,
+                cellular,
+                ramp,
+            } => {
+                let sample = Cellular {
+                    zoom: *zoom,
+                    speed: *speed,
+                    fractal: *fractal,
+                    cellular: *cellular,
+                }
+                .sample(&fragment, uniforms.time, context.noise);
+                noise_color(sample, colors, ramp.as_ref())
+            }
+            ShaderType::Texture { texture, sampler } => match textures.get_texture(*texture) {
+                Some(_) => TextureMap {
+                    texture: *texture,
+                    sampler: *sampler,
+                }
+                .shade(&fragment, colors, &acc, context),
+                // Same as animated textures, a missing texture skips the layer.
+                None => continue,
+            },
+            ShaderType::AnimatedTexture {
+                path,
+                fps,
+                looping,
+                sampler,
+            } => match textures.animated.get(path) {
+                Some(animation) => {
+                    let frame = animation.frame(animation.frame_at(uniforms.time, *fps, *looping));
+                    let lod = frame.lod(fragment.texture_dx, fragment.texture_dy);
+                    frame.sample(fragment.texture_position, lod, sampler)
+                }
+                None => continue,
+            },
+            ShaderType::Lighting => lighting_shader(&fragment, &acc, uniforms, lighting, material),
+            ShaderType::Atmosphere { density, falloff } => {
+                atmosphere_shader(&fragment, colors, uniforms, lighting, *density, *falloff)
+            }
+            ShaderType::RingBands { bands, gap } => {
+                let (color, alpha) = ring_bands_shader(&fragment, colors, *bands, *gap);
+                fragment.alpha *= alpha;
+                color
+            }
+            ShaderType::Custom { name } => match registry.get(name) {
+                Some(shader) => shader.shade(&fragment, colors, &acc, context),
+                // Unknown shaders are skipped, so a typo on a scene file doesn't crash the game.
+                None => continue,
+            },
+        };
+
+        acc = acc.blend(&color, blend_strategy);
+    }
+    let color = acc;
+
+    // Emitted light doesn't depend on the lights of the scene.
+    let emission = context.material.emission();
+    let color = color + emission;
+
+    Fragment {
+        color,
+        emission,
+        ..fragment
+    }
 }
 
-fn texture_shader(fragment: &Fragment, textures: &GameTextures, texture: Textures) -> Color {
-    let texture = textures.get_texture(texture);
-    texture.get_pixel_color(fragment.texture_position.x, fragment.texture_position.y)
+/// The color of the layer at `idx`, cycling through them. Layers without colors use white.
+pub(crate) fn layer_color(colors: &[Color], idx: usize) -> Color {
+    if colors.is_empty() {
+        return Color::white();
+    }
+
+    colors[idx % colors.len()]
 }
 
-fn fbm_shader(
+fn normal_map_shader(
     fragment: &Fragment,
-    uniforms: &Uniforms,
-    colors: &[Color],
-    speed: f32,
-    zoom: f32,
-    fractal: &FractalConfig,
-    noise: &mut FastNoiseLite,
-) -> Color {
-    let Uniforms { time, .. } = uniforms;
-    let FractalConfig {
-        octaves,
-        lacunarity,
-        gain,
-        weighted_strength,
-        f_type,
-    } = *fractal;
-
-    let x = fragment.vertex_position.x * zoom + speed * time;
-    let y = fragment.vertex_position.y * zoom;
-
-    noise.set_fractal_octaves(Some(octaves));
-    noise.set_fractal_gain(Some(gain));
-    noise.set_fractal_weighted_strength(Some(weighted_strength));
-    noise.set_noise_type(Some(fastnoise_lite::NoiseType::OpenSimplex2));
-    noise.set_fractal_type(Some(f_type));
-    noise.set_fractal_lacunarity(Some(lacunarity));
-
-    let noise_value = noise.get_noise_2d(x, y);
-    let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
-
-    colors[0] * intensity
+    textures: &GameTextures,
+    texture: Textures,
+    strength: f32,
+) -> Vec3 {
+    let Fragment {
+        normal, tangent, ..
+    } = fragment;
+
+    // Without a tangent there's no way to orient the normal map...
+    if *normal == Vec3::zeros() || *tangent == Vec3::zeros() {
+        return *normal;
+    }
+
+    let normal = normal.normalize();
+    let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    let Some(texture) = textures.get_texture(texture) else {
+        return normal;
+    };
+
+    // The colors of the normal map go from 0 to 255, the normals from -1 to 1.
+    let lod = texture.lod(fragment.texture_dx, fragment.texture_dy);
+    let Color { r, g, b } = texture.sample(fragment.texture_position, lod, &Sampler::default());
+    let to_direction = |channel: u8| channel as f32 / 255.0 * 2.0 - 1.0;
+    let mapped = tangent * to_direction(r) + bitangent * to_direction(g) + normal * to_direction(b);
+
+    normal.lerp(&mapped.normalize(), strength).normalize()
 }
 
-fn moving_stripes(
+fn noise_bump_shader(
     fragment: &Fragment,
-    stripe_width: f32,
-    speed: f32,
-    colors: &[Color],
     uniforms: &Uniforms,
-) -> Color {
-    let color1 = colors[0];
-    let color2 = colors[1];
-
-    let moving_y = fragment.vertex_position.y + uniforms.time * speed;
-
-    let stripe_factor = ((moving_y / stripe_width) * PI).sin() * 0.5 + 0.5;
-    color1.lerp(&color2, stripe_factor)
+    zoom: f32,
+    strength: f32,
+    fractal: &FractalConfig,
+    noise: &mut FastNoiseLite,
+) -> Vec3 {
+    let normal = fragment.normal;
+    if normal == Vec3::zeros() {
+        return normal;
+    }
+    let normal = normal.normalize();
+
+    configure_noise(noise, fractal);
+
+    // The height is sampled on the model position so the terrain moves along with the entity.
+    let position = fragment.vertex_position * zoom + fractal.offset;
+    let mut height = |offset: Vec3| noise_3d(noise, fractal, position + offset);
+    let gradient = Vec3::new(
+        height(Vec3::x() * BUMP_EPSILON) - height(-Vec3::x() * BUMP_EPSILON),
+        height(Vec3::y() * BUMP_EPSILON) - height(-Vec3::y() * BUMP_EPSILON),
+        height(Vec3::z() * BUMP_EPSILON) - height(-Vec3::z() * BUMP_EPSILON),
+    ) / (2.0 * BUMP_EPSILON)
+        * zoom;
+
+    // Gradients behave like normals when moving them into world space.
+    let gradient = create_normal_matrix(&uniforms.model_matrix) * gradient;
+    let surface_gradient = gradient - normal * normal.dot(&gradient);
+
+    (normal - surface_gradient * strength).normalize()
 }
 
 fn glowing_shader(
@@ -136,7 +388,8 @@ fn glowing_shader(
     )
 }
 
-fn interesting_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+#[allow(dead_code)]
+fn interesting_shader(fragment: &Fragment, _uniforms: &Uniforms) -> Color {
     let color1 = Color::red();
     let color2 = Color::green();
     let color3 = Color::blue();
@@ -157,12 +410,76 @@ fn interesting_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
         .lerp(&color1, wave3)
 }
 
-fn stripes_shader(fragment: &Fragment, stripe_width: f32, colors: &[Color]) -> Color {
-    let y = fragment.vertex_position.y;
-    // let y = fragment.position.y as usize;
+fn atmosphere_shader(
+    fragment: &Fragment,
+    colors: &[Color],
+    uniforms: &Uniforms,
+    lighting: &LightingContext,
+    density: f32,
+    falloff: f32,
+) -> Color {
+    if fragment.normal == Vec3::zeros() {
+        return Color::black();
+    }
+
+    let normal = fragment.normal.normalize();
+    let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+
+    // Fresnel, the more the surface faces away from the camera the thicker the atmosphere looks.
+    let rim = (1.0 - normal.dot(&view_dir).max(0.0)).powf(falloff);
+
+    // The air is lit a little past the terminator, light scatters around the planet.
+    let sunlight = lighting
+        .lights
+        .iter()
+        .map(|light| {
+            let (light_dir, intensity) = light.illuminate(&fragment.world_position);
+            intensity * smoothstep(-0.3, 0.5, normal.dot(&light_dir))
+        })
+        .sum::<f32>();
+    let brightness = lighting.ambient + sunlight;
+
+    layer_color(colors, 0) * (rim * density * brightness)
+}
+
+fn ring_bands_shader(fragment: &Fragment, colors: &[Color], bands: f32, gap: f32) -> (Color, f32) {
+    let t = fragment.texture_position.x.clamp(0.0, 1.0);
+    let band = t * bands;
+    let band_idx = band as usize;
 
-    let stripe_idx = (y / stripe_width).abs() as usize % colors.len();
-    colors[stripe_idx]
+    // The gap is at the start of each band, with soft borders.
+    let coverage = smoothstep(gap, (gap + 0.1).min(1.0), band.fract());
+    // Bands aren't all equally dense...
+    let density = 0.55 + 0.45 * (band_idx as f32 * 2.3).sin().abs();
+    // ...and the ring fades on both edges.
+    let edges = smoothstep(0.0, 0.05, t) * (1.0 - smoothstep(0.9, 1.0, t));
+
+    let color =
+        layer_color(colors, band_idx).lerp(&layer_color(colors, band_idx + 1), band.fract());
+
+    (color, coverage * density * edges)
+}
+
+fn lighting_shader(
+    fragment: &Fragment,
+    current_color: &Color,
+    uniforms: &Uniforms,
+    lighting: &LightingContext,
+    material: &Material,
+) -> Color {
+    // Nothing to light...
+    if fragment.normal == Vec3::zeros() {
+        return *current_color;
+    }
+
+    blinn_phong(
+        current_color,
+        &fragment.world_position,
+        &fragment.normal,
+        &uniforms.camera_position,
+        lighting,
+        material,
+    )
 }
 
 fn intensity_shader(fragment: &Fragment, current_color: &Color) -> Color {
@@ -171,46 +488,80 @@ fn intensity_shader(fragment: &Fragment, current_color: &Color) -> Color {
     *current_color * *intensity
 }
 
-fn cellular_shader(
-    fragment: &Fragment,
-    uniforms: &Uniforms,
-    colors: &[Color],
-    speed: f32,
-    zoom: f32,
-    fractal: &FractalConfig,
-    cellular: &CellularConfig,
-    noise: &mut FastNoiseLite,
-) -> Color {
-    let Uniforms { time, .. } = uniforms;
-    let FractalConfig {
-        octaves,
-        lacunarity,
-        gain,
-        weighted_strength,
-        f_type,
-    } = *fractal;
-    let CellularConfig {
-        distance_func,
-        return_type,
-        jitter,
-    } = *cellular;
-
-    let x = fragment.vertex_position.x * zoom + speed * time;
-    let y = fragment.vertex_position.y * zoom;
-
-    noise.set_noise_type(Some(fastnoise_lite::NoiseType::Cellular));
-    noise.set_fractal_octaves(Some(octaves));
-    noise.set_fractal_gain(Some(gain));
-    noise.set_fractal_weighted_strength(Some(weighted_strength));
-    noise.set_fractal_type(Some(f_type));
-    noise.set_fractal_lacunarity(Some(lacunarity));
-
-    noise.set_cellular_distance_function(Some(distance_func));
-    noise.set_cellular_return_type(Some(return_type));
-    noise.set_cellular_jitter(Some(jitter));
-
-    let noise_value = noise.get_noise_2d(x, y);
-    let intensity = clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), noise_value);
-
-    colors[0] * intensity
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::{vec2, Mat4};
+
+    use super::*;
+    use crate::{color::blenders::BlendMode, vertex::shader::create_noise};
+
+    /// Inverts the color of the previous layers.
+    struct Invert;
+
+    impl FragmentShader for Invert {
+        fn shade(
+            &self,
+            _fragment: &Fragment,
+            _colors: &[Color],
+            current_color: &Color,
+            _context: &mut ShaderContext,
+        ) -> Color {
+            Color::white() - *current_color
+        }
+    }
+
+    #[test]
+    fn test_custom_shaders_by_name() {
+        let mut registry = ShaderRegistry::with_builtins();
+        registry.register("invert", Invert);
+
+        let uniforms = Uniforms {
+            camera_position: Vec3::zeros(),
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 0.0,
+        };
+        let lighting = LightingContext {
+            ambient: 0.0,
+            lights: &[],
+            shadow_maps: &[],
+            occluders: &[],
+        };
+        let mut noise = create_noise();
+        let mut context = ShaderContext {
+            uniforms: &uniforms,
+            textures: &GameTextures::new("assets/textures/"),
+            lighting: &lighting,
+            material: &Material::default(),
+            noise: &mut noise,
+        };
+
+        let custom = |name: &str| {
+            (
+                ShaderType::Custom {
+                    name: name.to_string(),
+                },
+                vec![],
+                BlendMode::Replace,
+            )
+        };
+        let layers = vec![
+            (
+                ShaderType::BaseColor,
+                vec![Color::red()],
+                BlendMode::Replace,
+            ),
+            custom("invert"),
+            // Not registered, gets skipped.
+            custom("missing"),
+        ];
+
+        let fragment = Fragment::new(vec2(0.0, 0.0), Color::black(), 0.0, Vec3::zeros());
+        let shaded = fragment_shader(fragment, &layers, &registry, &mut context);
+
+        assert_eq!(shaded.color, Color::new(0, 255, 255));
+        assert!(registry.get("fbm").is_some());
+    }
 }
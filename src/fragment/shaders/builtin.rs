@@ -0,0 +1,435 @@
+//! The shaders that come with the engine.
+//! Each `ShaderType` with the same name uses them, and `ShaderRegistry::with_builtins`
+//! registers an instance of each one so `ShaderType::Custom` layers can use them too.
+
+use std::f32::consts::PI;
+
+use fastnoise_lite::{FastNoiseLite, FractalType};
+use nalgebra_glm::{vec2, vec3, Vec3};
+
+use crate::{
+    clamp_with_universe,
+    color::{ramp::ColorRamp, Color},
+    fragment::Fragment,
+    texture::{sampler::Sampler, Textures},
+    vertex::shader::{CellularConfig, DomainWarpConfig, FractalConfig},
+};
+
+use super::{layer_color, FragmentShader, ShaderContext};
+
+/// Horizontal stripes, cycling through all the colors of the layer.
+#[derive(Debug, Clone, Copy)]
+pub struct Stripes {
+    pub stripe_width: f32,
+}
+
+impl FragmentShader for Stripes {
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        colors: &[Color],
+        _current_color: &Color,
+        _context: &mut ShaderContext,
+    ) -> Color {
+        let y = fragment.vertex_position.y;
+
+        let stripe_idx = (y / self.stripe_width).abs() as usize;
+        layer_color(colors, stripe_idx)
+    }
+}
+
+/// Stripes that fade between the first two colors and move upwards.
+#[derive(Debug, Clone, Copy)]
+pub struct MovingStripes {
+    pub stripe_width: f32,
+    pub speed: f32,
+}
+
+impl FragmentShader for MovingStripes {
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        colors: &[Color],
+        _current_color: &Color,
+        context: &mut ShaderContext,
+    ) -> Color {
+        let color1 = layer_color(colors, 0);
+        let color2 = layer_color(colors, 1);
+
+        let moving_y = fragment.vertex_position.y + context.uniforms.time * self.speed;
+
+        let stripe_factor = ((moving_y / self.stripe_width) * PI).sin() * 0.5 + 0.5;
+        color1.lerp(&color2, stripe_factor)
+    }
+}
+
+/// Fractal noise, multiplies the first color by the value of the noise.
+#[derive(Debug, Clone, Copy)]
+pub struct FBm {
+    pub zoom: f32,
+    pub speed: f32,
+    pub fractal: FractalConfig,
+}
+
+impl FBm {
+    /// Computes the intensity of the noise on the fragment, `FBm::sample` mapped by `clamp_with_universe`.
+    pub fn value(&self, fragment: &Fragment, time: f32, noise: &mut FastNoiseLite) -> f32 {
+        clamp_with_universe(
+            vec2(-1.0, 1.0),
+            vec2(0.0, 1.0),
+            self.sample(fragment, time, noise),
+        )
+    }
+
+    /// Samples the noise on the fragment, from -1 to 1.
+    pub fn sample(&self, fragment: &Fragment, time: f32, noise: &mut FastNoiseLite) -> f32 {
+        let FBm {
+            zoom,
+            speed,
+            fractal,
+        } = *self;
+
+        configure_noise(noise, &fractal);
+
+        sample_noise(
+            noise,
+            &fractal,
+            &fragment.vertex_position,
+            zoom,
+            speed * time,
+        )
+    }
+}
+
+impl FragmentShader for FBm {
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        colors: &[Color],
+        _current_color: &Color,
+        context: &mut ShaderContext,
+    ) -> Color {
+        noise_color(
+            self.sample(fragment, context.uniforms.time, context.noise),
+            colors,
+            None,
+        )
+    }
+}
+
+/// Cellular (Voronoi) noise, multiplies the first color by the value of the noise.
+#[derive(Debug, Clone, Copy)]
+pub struct Cellular {
+    pub zoom: f32,
+    pub speed: f32,
+    pub fractal: FractalConfig,
+    pub cellular: CellularConfig,
+}
+
+impl Cellular {
+    /// Samples the noise on the fragment, from -1 to 1.
+    pub fn sample(&self, fragment: &Fragment, time: f32, noise: &mut FastNoiseLite) -> f32 {
+        let Cellular {
+            zoom,
+            speed,
+            fractal,
+            cellular,
+        } = *self;
+        let CellularConfig {
+            distance_func,
+            return_type,
+            jitter,
+        } = cellular;
+
+        configure_noise(noise, &fractal);
+        noise.set_noise_type(Some(fastnoise_lite::NoiseType::Cellular));
+        noise.set_cellular_distance_function(Some(distance_func));
+        noise.set_cellular_return_type(Some(return_type));
+        noise.set_cellular_jitter(Some(jitter));
+
+        sample_noise(
+            noise,
+            &fractal,
+            &fragment.vertex_position,
+            zoom,
+            speed * time,
+        )
+    }
+}
+
+impl FragmentShader for Cellular {
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        colors: &[Color],
+        _current_color: &Color,
+        context: &mut ShaderContext,
+    ) -> Color {
+        noise_color(
+            self.sample(fragment, context.uniforms.time, context.noise),
+            colors,
+            None,
+        )
+    }
+}
+
+/// Colors a noise sample from -1 to 1 with the ramp, spread over its whole 0 to 1 range.
+/// Without one it multiplies the first color by the intensity of the noise, like `FBm::value`.
+pub(crate) fn noise_color(sample: f32, colors: &[Color], ramp: Option<&ColorRamp>) -> Color {
+    match ramp {
+        Some(ramp) => ramp.sample(sample * 0.5 + 0.5),
+        None => {
+            layer_color(colors, 0) * clamp_with_universe(vec2(-1.0, 1.0), vec2(0.0, 1.0), sample)
+        }
+    }
+}
+
+/// Sets up the noise with everything inside the config.
+pub(crate) fn configure_noise(noise: &mut FastNoiseLite, fractal: &FractalConfig) {
+    let FractalConfig {
+        octaves,
+        lacunarity,
+        gain,
+        weighted_strength,
+        f_type,
+        noise_type,
+        seed,
+        warp,
+        ..
+    } = *fractal;
+
+    noise.set_seed(Some(seed));
+    noise.set_noise_type(Some(noise_type));
+    noise.set_fractal_type(Some(f_type));
+    noise.set_fractal_octaves(Some(octaves));
+    noise.set_fractal_gain(Some(gain));
+    noise.set_fractal_weighted_strength(Some(weighted_strength));
+    noise.set_fractal_lacunarity(Some(lacunarity));
+
+    if let Some(DomainWarpConfig {
+        warp_type,
+        amplitude,
+        ..
+    }) = warp
+    {
+        noise.set_domain_warp_type(Some(warp_type));
+        noise.set_domain_warp_amp(Some(amplitude));
+    }
+}
+
+/// Samples the noise on the given model position, following `FractalConfig::spherical`.
+/// The noise must already be configured with `configure_noise`.
+///
+/// * `shift`: How much the noise moves along the X axis, used to animate it.
+pub(crate) fn sample_noise(
+    noise: &mut FastNoiseLite,
+    fractal: &FractalConfig,
+    position: &Vec3,
+    zoom: f32,
+    shift: f32,
+) -> f32 {
+    let FractalConfig {
+        spherical, offset, ..
+    } = fractal;
+
+    if *spherical {
+        let direction = position
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(Vec3::zeros());
+        noise_3d(
+            noise,
+            fractal,
+            direction * zoom + offset + vec3(shift, 0.0, 0.0),
+        )
+    } else {
+        let (mut x, mut y) = (
+            position.x * zoom + shift + offset.x,
+            position.y * zoom + offset.y,
+        );
+        if let Some(warp) = fractal.warp {
+            noise.set_fractal_type(Some(warp.f_type));
+            (x, y) = noise.domain_warp_2d(x, y);
+            noise.set_fractal_type(Some(fractal.f_type));
+        }
+        sample_octaves(noise, fractal, |noise, frequency| {
+            noise.get_noise_2d(x * frequency, y * frequency)
+        })
+    }
+}
+
+/// Samples the noise in 3D, warping the point first if the config has a `DomainWarpConfig`.
+/// The noise must already be configured with `configure_noise`.
+pub(crate) fn noise_3d(noise: &mut FastNoiseLite, fractal: &FractalConfig, point: Vec3) -> f32 {
+    let (mut x, mut y, mut z) = (point.x, point.y, point.z);
+    if let Some(warp) = fractal.warp {
+        // The warp reads its fractal type from the noise too.
+        noise.set_fractal_type(Some(warp.f_type));
+        (x, y, z) = noise.domain_warp_3d(x, y, z);
+        noise.set_fractal_type(Some(fractal.f_type));
+    }
+    sample_octaves(noise, fractal, |noise, frequency| {
+        noise.get_noise_3d(x * frequency, y * frequency, z * frequency)
+    })
+}
+
+/// Reads the noise, folding each octave on its own if the config is `FractalConfig::billow`.
+///
+/// * `sample`: Reads the noise with the point scaled by the given frequency.
+fn sample_octaves(
+    noise: &mut FastNoiseLite,
+    fractal: &FractalConfig,
+    sample: impl Fn(&mut FastNoiseLite, f32) -> f32,
+) -> f32 {
+    if !fractal.billow {
+        return sample(noise, 1.0);
+    }
+
+    // The noise would sum the octaves before they can be folded, so they're summed here instead.
+    noise.set_fractal_type(Some(FractalType::None));
+    let (mut amplitude, mut frequency) = (1.0, 1.0);
+    let (mut sum, mut total_amplitude) = (0.0, 0.0);
+    for _ in 0..fractal.octaves.max(1) {
+        let value = sample(noise, frequency).abs() * 2.0 - 1.0;
+        sum += value * amplitude;
+        total_amplitude += amplitude;
+
+        // Same as `FractalType::FBm`, brighter octaves make the next ones stronger.
+        let weight =
+            1.0 - fractal.weighted_strength + fractal.weighted_strength * (value + 1.0) * 0.5;
+        amplitude *= fractal.gain * weight;
+        frequency *= fractal.lacunarity;
+    }
+    noise.set_fractal_type(Some(fractal.f_type));
+
+    sum / total_amplitude
+}
+
+/// Samples one of the `GameTextures` with the texture coordinates of the fragment.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureMap {
+    pub texture: Textures,
+    pub sampler: Sampler,
+}
+
+impl FragmentShader for TextureMap {
+    fn shade(
+        &self,
+        fragment: &Fragment,
+        _colors: &[Color],
+        current_color: &Color,
+        context: &mut ShaderContext,
+    ) -> Color {
+        let Some(texture) = context.textures.get_texture(self.texture) else {
+            return *current_color;
+        };
+        let lod = texture.lod(fragment.texture_dx, fragment.texture_dy);
+        texture.sample(fragment.texture_position, lod, &self.sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex::shader::{create_noise, NOISE_SEED};
+
+    fn test_fractal() -> FractalConfig {
+        FractalConfig {
+            octaves: 3,
+            lacunarity: 2.0,
+            gain: 0.5,
+            weighted_strength: 0.0,
+            f_type: fastnoise_lite::FractalType::FBm,
+            noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+            seed: NOISE_SEED,
+            billow: false,
+            warp: None,
+            spherical: false,
+            offset: Vec3::zeros(),
+        }
+    }
+
+    #[test]
+    fn test_spherical_noise_isnt_mirrored() {
+        let mut noise = create_noise();
+        let flat = test_fractal();
+        let spherical = FractalConfig {
+            spherical: true,
+            ..flat
+        };
+        let front = vec3(0.3, 0.4, 0.866);
+        let back = vec3(0.3, 0.4, -0.866);
+        let mut sample = |fractal: &FractalConfig, position: &Vec3| {
+            configure_noise(&mut noise, fractal);
+            sample_noise(&mut noise, fractal, position, 200.0, 0.0)
+        };
+
+        // On the XY plane the back of the sphere is a copy of the front...
+        assert_eq!(sample(&flat, &front), sample(&flat, &back));
+        // ...but on the sphere every point is different.
+        assert_ne!(sample(&spherical, &front), sample(&spherical, &back));
+
+        let moved = FractalConfig {
+            offset: vec3(1000.0, 0.0, 0.0),
+            ..spherical
+        };
+        assert_ne!(sample(&spherical, &front), sample(&moved, &front));
+    }
+
+    #[test]
+    fn test_noise_config_changes_the_noise() {
+        let mut noise = create_noise();
+        let plain = FractalConfig {
+            spherical: true,
+            ..test_fractal()
+        };
+        let position = vec3(0.3, 0.4, 0.866);
+        let mut sample = |fractal: &FractalConfig| {
+            configure_noise(&mut noise, fractal);
+            sample_noise(&mut noise, fractal, &position, 200.0, 0.0)
+        };
+        let value = sample(&plain);
+
+        let seeded = FractalConfig { seed: 1, ..plain };
+        assert_ne!(sample(&seeded), value);
+
+        let warped = FractalConfig {
+            warp: Some(DomainWarpConfig {
+                warp_type: fastnoise_lite::DomainWarpType::OpenSimplex2,
+                amplitude: 30.0,
+                f_type: fastnoise_lite::FractalType::DomainWarpProgressive,
+            }),
+            ..plain
+        };
+        assert_ne!(sample(&warped), value);
+    }
+
+    #[test]
+    fn test_billow_folds_every_octave() {
+        let mut noise = create_noise();
+        let plain = test_fractal();
+        let billow = FractalConfig {
+            billow: true,
+            ..plain
+        };
+        let mut sample = |fractal: &FractalConfig, position: &Vec3| {
+            configure_noise(&mut noise, fractal);
+            sample_noise(&mut noise, fractal, position, 200.0, 0.0)
+        };
+
+        let positions: Vec<Vec3> = (0..100)
+            .map(|i| vec3(i as f32 * 0.37, (i as f32 * 0.71).sin(), 0.0))
+            .collect();
+        let mut differences = 0;
+        for position in &positions {
+            let folded = sample(&billow, position);
+            assert!((-1.0..=1.0).contains(&folded));
+
+            let value = sample(&plain, position);
+            // Folding the sum of the octaves isn't the same as folding each one.
+            if folded != value && folded != value.abs() * 2.0 - 1.0 {
+                differences += 1;
+            }
+        }
+        assert!(differences > positions.len() / 2);
+    }
+}
@@ -1,18 +1,22 @@
 use std::f32::consts::PI;
 
-use nalgebra_glm::{vec3, Vec3};
+use nalgebra_glm::{vec3, Quat, Vec3};
 
 use crate::{
     camera::Camera,
     color::{blenders::BlendMode, Color},
-    obj::load_objs,
+    light::{Attenuation, Light, LightKind},
+    obj::{load_objs, MeshSource},
     vertex::shader::{create_model_matrix, ShaderType},
     Entity, EntityModel, EntityOptimizations, EntityShader, Ship,
 };
 
+use super::material::METAL;
 use super::shaders;
 
-pub const ORIGINAL_ROTATION: Vec3 = Vec3::new(0.0, PI, 0.0);
+/// Half a turn around the Y axis, so the ship faces away from the camera.
+pub const ORIGINAL_ORIENTATION: Quat = Quat::new(0.0, 0.0, 1.0, 0.0);
+const SHIP_OBJ: &str = "assets/models/BlueFalcon.obj";
 
 fn ship_shaders() -> Vec<EntityShader> {
     vec![
@@ -27,29 +31,58 @@ fn ship_shaders() -> Vec<EntityShader> {
                 speed: 0.0,
                 fractal: crate::vertex::shader::FractalConfig {
                     f_type: fastnoise_lite::FractalType::None,
+                    noise_type: fastnoise_lite::NoiseType::OpenSimplex2,
+                    seed: crate::vertex::shader::NOISE_SEED,
+                    billow: false,
+                    warp: None,
                     octaves: 4,
                     lacunarity: 0.5,
                     gain: 1.0,
                     weighted_strength: 0.0,
+                    spherical: false,
+                    offset: Vec3::zeros(),
                 },
                 cellular: crate::vertex::shader::CellularConfig {
                     distance_func: fastnoise_lite::CellularDistanceFunction::EuclideanSq,
                     return_type: fastnoise_lite::CellularReturnType::Distance,
                     jitter: 1.0,
                 },
+                ramp: None,
             },
             vec![0xff002b.into()],
             BlendMode::IgnoreWhiteAdd,
         ),
+        (ShaderType::Lighting, vec![], BlendMode::Replace),
     ]
 }
 
+/// A spot light that illuminates what's in front of the ship.
+fn headlight() -> Light {
+    Light {
+        kind: LightKind::Spot {
+            direction: vec3(0.0, 0.0, 1.0),
+            inner_angle: PI / 12.0,
+            outer_angle: PI / 6.0,
+            attenuation: Attenuation {
+                constant: 1.0,
+                linear: 0.05,
+                quadratic: 0.01,
+            },
+        },
+        position: Vec3::zeros(),
+        color: Color::new(255, 244, 214),
+        intensity: 2.0,
+        radius: 0.0,
+        casts_shadows: false,
+    }
+}
+
 pub fn create_ship(initial_world_position: Vec3) -> Ship {
-    let ship_obj = load_objs("assets/models/BlueFalcon.obj").unwrap();
+    let ship_obj = load_objs(SHIP_OBJ).unwrap();
 
     let shaders = ship_shaders();
     let scale = 0.2;
-    let rotation = ORIGINAL_ROTATION;
+    let orientation = ORIGINAL_ORIENTATION;
     let translation = initial_world_position;
     let optimizations = EntityOptimizations {
         camera_direction: false,
@@ -57,19 +90,25 @@ pub fn create_ship(initial_world_position: Vec3) -> Ship {
     };
 
     let entity = Entity {
+        name: Some("ship".to_string()),
         wireframe_color: Some(Color::white()),
         ellipsis: None,
         objs: ship_obj,
+        mesh: Some(MeshSource::Obj(SHIP_OBJ.to_string())),
         use_screen_position: true,
         shaders,
         optimizations,
-        model_matrix: create_model_matrix(translation, scale, rotation),
+        model_matrix: create_model_matrix(translation, scale, orientation),
         model: crate::EntityModel {
-            rotation,
+            orientation,
             scale,
             translation,
         },
         custom_depth: None,
+        material: METAL,
+        lights: vec![headlight()],
+        parent: None,
+        transparent: false,
     };
 
     Ship {
@@ -80,7 +119,7 @@ pub fn create_ship(initial_world_position: Vec3) -> Ship {
 }
 
 pub fn create_ship_from(other_ship: &Ship) -> Ship {
-    let ship_obj = load_objs("assets/models/BlueFalcon.obj").unwrap();
+    let ship_obj = load_objs(SHIP_OBJ).unwrap();
     let shaders = ship_shaders();
     let optimizations = EntityOptimizations {
         camera_direction: false,
@@ -88,29 +127,35 @@ pub fn create_ship_from(other_ship: &Ship) -> Ship {
     };
 
     let EntityModel {
-        rotation,
+        orientation,
         scale,
         translation,
     } = other_ship.entity.model;
 
-    let model_matrix = create_model_matrix(translation, scale, rotation);
+    let model_matrix = create_model_matrix(translation, scale, orientation);
 
     let model = EntityModel {
-        rotation,
+        orientation,
         scale,
         translation,
     };
 
     let entity = Entity {
+        name: Some("ship".to_string()),
         wireframe_color: Some(Color::white()),
         model,
         model_matrix,
         ellipsis: None,
         objs: ship_obj,
+        mesh: Some(MeshSource::Obj(SHIP_OBJ.to_string())),
         use_screen_position: false,
         shaders,
         optimizations,
         custom_depth: None,
+        material: METAL,
+        lights: vec![headlight()],
+        parent: None,
+        transparent: false,
     };
 
     Ship {
@@ -1,6 +1,6 @@
 use core::f32;
 
-use crate::{bmp::write_bmp_file, color::Color, texture::Texture};
+use crate::{bmp::write_bmp_file, color::Color, texture::Texture, viewport::Rect};
 
 type Buffer = Vec<u32>;
 
@@ -14,6 +14,11 @@ pub struct Framebuffer {
     empty_buffer: Buffer,
     z_buffer: Vec<f32>,
     empty_z_buffer: Vec<f32>,
+    /// If some, only the points inside this rectangle get painted.
+    scissor: Option<Rect>,
+    /// Light emitted by each pixel, gets blurred and added on top by `apply_bloom`.
+    glow_buffer: Vec<Color>,
+    current_glow: Color,
 }
 
 fn create_filled_buffer(width: &usize, height: &usize, color: &Color) -> Buffer {
@@ -64,6 +69,9 @@ impl Framebuffer {
             empty_buffer,
             z_buffer,
             empty_z_buffer,
+            scissor: None,
+            glow_buffer: vec![Color::black(); width * height],
+            current_glow: Color::black(),
         }
     }
 
@@ -73,6 +81,62 @@ impl Framebuffer {
     pub fn clear(&mut self) {
         self.buffer.clone_from(&self.empty_buffer);
         self.z_buffer.clone_from(&self.empty_z_buffer);
+        self.glow_buffer.fill(Color::black());
+    }
+
+    /// Clears only the pixels inside the given rectangle, using the background color.
+    pub fn clear_rect(&mut self, rect: &Rect) {
+        let Framebuffer {
+            width,
+            height,
+            buffer,
+            background_color,
+            z_buffer,
+            glow_buffer,
+            ..
+        } = self;
+        let color: u32 = background_color.into();
+
+        for y in rect.y..(rect.y + rect.height).min(*height) {
+            let start = y * *width + rect.x.min(*width);
+            let end = y * *width + (rect.x + rect.width).min(*width);
+            buffer[start..end].fill(color);
+            z_buffer[start..end].fill(f32::NEG_INFINITY);
+            glow_buffer[start..end].fill(Color::black());
+        }
+    }
+
+    /// Paints the border of the given rectangle with the `current_color`, ignoring depth.
+    pub fn paint_rect_outline(&mut self, rect: &Rect) {
+        let Framebuffer {
+            width,
+            height,
+            buffer,
+            current_color,
+            ..
+        } = self;
+        let color: u32 = current_color.into();
+        let max_x = (rect.x + rect.width).min(*width).saturating_sub(1);
+        let max_y = (rect.y + rect.height).min(*height).saturating_sub(1);
+
+        for x in rect.x..=max_x {
+            buffer[rect.y * *width + x] = color;
+            buffer[max_y * *width + x] = color;
+        }
+        for y in rect.y..=max_y {
+            buffer[y * *width + rect.x] = color;
+            buffer[y * *width + max_x] = color;
+        }
+    }
+
+    /// Restricts the points that can be painted to the ones inside `scissor`.
+    /// `None` allows painting on the whole framebuffer.
+    pub fn set_scissor(&mut self, scissor: Option<Rect>) {
+        self.scissor = scissor;
+    }
+
+    pub fn scissor(&self) -> Option<Rect> {
+        self.scissor
     }
 
     /// Saves the current framebuffer as a background.
@@ -86,23 +150,80 @@ impl Framebuffer {
     ///
     /// The paint origin is located on the top left corner of the window.
     ///
-    /// The color used is the one provided by `current_color`.
+    /// The color used is the one provided by `current_color`,
+    /// and the light it emits is the one provided by `current_glow`.
     pub fn paint_point(
         &mut self,
         point: nalgebra_glm::Vec2,
         depth: f32,
     ) -> Result<(), PaintPointErrors> {
+        if let Some(idx) = self.point_index(point)? {
+            let Framebuffer {
+                buffer,
+                current_color,
+                z_buffer,
+                glow_buffer,
+                current_glow,
+                ..
+            } = self;
+
+            if depth > z_buffer[idx] {
+                z_buffer[idx] = depth;
+                buffer[idx] = current_color.into();
+                glow_buffer[idx] = *current_glow;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Blends the `current_color` over the point, if it's in front of what's already there.
+    /// Unlike `paint_point` the depth isn't stored, so whatever is painted later
+    /// behind this point still shows up.
+    ///
+    /// * `alpha`: How much of the `current_color` is used, from 0 to 1.
+    pub fn blend_point(
+        &mut self,
+        point: nalgebra_glm::Vec2,
+        depth: f32,
+        alpha: f32,
+    ) -> Result<(), PaintPointErrors> {
+        if let Some(idx) = self.point_index(point)? {
+            let Framebuffer {
+                buffer,
+                current_color,
+                z_buffer,
+                glow_buffer,
+                current_glow,
+                ..
+            } = self;
+
+            if depth > z_buffer[idx] {
+                let behind = Color::from(buffer[idx]);
+                buffer[idx] = behind.lerp(current_color, alpha).into();
+                glow_buffer[idx] = glow_buffer[idx].lerp(current_glow, alpha);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the index of the point on the buffers.
+    /// Returns `None` if the point is outside the scissor.
+    fn point_index(&self, point: nalgebra_glm::Vec2) -> Result<Option<usize>, PaintPointErrors> {
         let Framebuffer {
             width,
             height,
-            buffer,
-            current_color,
-            z_buffer,
+            scissor,
             ..
         } = self;
         let x = point.x;
         let y = point.y;
 
+        if scissor.is_some_and(|rect| !rect.contains(x.round(), y.round())) {
+            return Ok(None);
+        }
+
         if x < 0.0 {
             Err(PaintPointErrors::XTooSmall)?
         }
@@ -117,14 +238,7 @@ impl Framebuffer {
         match (x < *width, y < *height) {
             (false, _) => Err(PaintPointErrors::XTooLarge),
             (_, false) => Err(PaintPointErrors::YTooLarge),
-            _ => {
-                let idx = y * *width + x;
-                if depth > z_buffer[idx] {
-                    z_buffer[idx] = depth;
-                    buffer[idx] = current_color.into();
-                }
-                Ok(())
-            }
+            _ => Ok(Some(y * *width + x)),
         }
     }
 
@@ -193,6 +307,49 @@ impl Framebuffer {
         self.current_color = new_color.into();
     }
 
+    /// Sets the `current_glow` property, black means the points don't emit light.
+    ///
+    /// * `new_glow`: The emitted light to apply.
+    pub fn set_current_glow(&mut self, new_glow: impl Into<Color>) {
+        self.current_glow = new_glow.into();
+    }
+
+    /// Blurs the light emitted by the painted points and adds it on top of the buffer,
+    /// so bright surfaces bleed into their surroundings.
+    ///
+    /// * `radius`: How many pixels the light spreads.
+    /// * `strength`: Multiplies the blurred light before adding it.
+    pub fn apply_bloom(&mut self, radius: usize, strength: f32) {
+        let Framebuffer {
+            width,
+            height,
+            buffer,
+            glow_buffer,
+            ..
+        } = self;
+        let (width, height) = (*width, *height);
+
+        if glow_buffer.iter().all(|c| c.is_black()) {
+            return;
+        }
+
+        let glow: Vec<[f32; 3]> = glow_buffer
+            .iter()
+            .map(|c| [c.r as f32, c.g as f32, c.b as f32])
+            .collect();
+        let horizontal = box_blur(&glow, width, height, radius, (1, 0));
+        let blurred = box_blur(&horizontal, width, height, radius, (0, 1));
+
+        for (pixel, [r, g, b]) in buffer.iter_mut().zip(blurred) {
+            let glow = Color::new(
+                (r * strength).clamp(0.0, 255.0) as u8,
+                (g * strength).clamp(0.0, 255.0) as u8,
+                (b * strength).clamp(0.0, 255.0) as u8,
+            );
+            *pixel = (Color::from(*pixel) + glow).into();
+        }
+    }
+
     /// Saves the pixel data into a .bmp located in the given `file_path`.
     pub fn save(&self, file_path: &str) -> std::io::Result<()> {
         let Framebuffer {
@@ -205,3 +362,41 @@ impl Framebuffer {
         write_bmp_file(file_path, buffer, *width, *height)
     }
 }
+
+/// Averages each pixel with its neighbors along a single direction.
+///
+/// * `step`: `(1, 0)` blurs horizontally and `(0, 1)` blurs vertically.
+fn box_blur(
+    pixels: &[[f32; 3]],
+    width: usize,
+    height: usize,
+    radius: usize,
+    step: (usize, usize),
+) -> Vec<[f32; 3]> {
+    let samples = (2 * radius + 1) as f32;
+
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let mut sum = [0.0; 3];
+
+            for offset in 0..=2 * radius {
+                let sx = (x + offset * step.0).checked_sub(radius * step.0);
+                let sy = (y + offset * step.1).checked_sub(radius * step.1);
+                let (Some(sx), Some(sy)) = (sx, sy) else {
+                    continue;
+                };
+                if sx >= width || sy >= height {
+                    continue;
+                }
+
+                let pixel = pixels[sy * width + sx];
+                for (channel, value) in sum.iter_mut().zip(pixel) {
+                    *channel += value;
+                }
+            }
+
+            sum.map(|channel| channel / samples)
+        })
+        .collect()
+}
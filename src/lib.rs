@@ -5,20 +5,34 @@ pub mod fragment;
 pub mod framebuffer;
 pub mod light;
 pub mod obj;
+pub mod picking;
 pub mod render;
+pub mod scene;
+pub mod shadow;
 pub mod skybox;
 pub mod texture;
 pub mod vertex;
+pub mod viewport;
 
-use camera::Camera;
+use camera::{
+    chase::ChaseCamera,
+    path::{CameraPath, PathPlayback},
+    target::OrbitLock,
+    Camera,
+};
 use color::{blenders::BlendMode, Color};
-use fragment::ellipsis::Ellipsis;
-use light::Light;
-use nalgebra_glm::{Mat4, Vec2, Vec3};
-use obj::Obj;
+use fragment::{ellipsis::Ellipsis, material::Material, shaders::ShaderRegistry};
+use light::{AmbientLightIntensity, Light};
+use nalgebra_glm::{
+    quat_angle_axis, quat_normalize, quat_rotate_vec3, vec3, Mat4, Quat, Vec2, Vec3,
+};
+use obj::{MeshSource, Obj};
+use picking::PickResult;
+use serde::{Deserialize, Serialize};
 use skybox::Skybox;
 use texture::GameTextures;
 use vertex::shader::{create_model_matrix, ShaderType, Uniforms};
+use viewport::ViewportLayout;
 
 pub fn equal(a: f32, b: f32, eps: f32) -> bool {
     (a - b).abs() < eps
@@ -38,19 +52,72 @@ pub enum Message {
     ResizeWindow((usize, usize)),
     AlternateView,
     StopShip,
+    /// Rotates the ship around its local axes, `x` is the pitch, `y` is the yaw and `z` is the roll.
     RotateShip(Vec3),
     StartGame,
     ViewControls,
+    /// Starts playing the camera path, or stops it if it's already playing.
+    ToggleCameraPath,
+    /// Detaches the camera from the ship.
+    FreeFlyView,
+    /// Moves the free fly camera, `x` goes right, `y` goes up and `z` goes forward.
+    /// The deltas get multiplied by the free fly speed.
+    MoveFreeCamera(Vec3),
+    /// Multiplies the free fly speed by the given factor.
+    ChangeFreeFlySpeed(f32),
+    /// Restores the ship and camera saved in `previous_fpv_state`.
+    SnapToShip,
+    /// Changes the entity the `ViewType::BirdEye` camera orbits around by the given step.
+    CycleTarget(isize),
+    /// Selects the entity under the given framebuffer position.
+    PickEntity(Vec2),
+    /// Changes to the next `ViewportLayout`.
+    CycleViewportLayout,
 }
 
 pub type EntityShader = (ShaderType, Vec<Color>, BlendMode);
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityModel {
-    pub rotation: Vec3,
+    pub orientation: Quat,
     pub scale: f32,
     pub translation: Vec3,
 }
 
+impl EntityModel {
+    /// The direction the model is facing in world space.
+    /// The front of a model is its +Z axis.
+    pub fn forward(&self) -> Vec3 {
+        self.local_to_world_direction(vec3(0.0, 0.0, 1.0))
+    }
+
+    /// The up direction of the model in world space.
+    pub fn up(&self) -> Vec3 {
+        self.local_to_world_direction(vec3(0.0, 1.0, 0.0))
+    }
+
+    /// The right direction of the model in world space.
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(&self.up()).normalize()
+    }
+
+    /// Computes the orientation after rotating around the local axes of the model.
+    ///
+    /// * `delta`: Radians to rotate, `x` is the pitch, `y` is the yaw and `z` is the roll.
+    pub fn rotated_locally(&self, delta: Vec3) -> Quat {
+        let pitch = quat_angle_axis(delta.x, &Vec3::x());
+        let yaw = quat_angle_axis(delta.y, &Vec3::y());
+        let roll = quat_angle_axis(delta.z, &Vec3::z());
+
+        quat_normalize(&(self.orientation * yaw * pitch * roll))
+    }
+
+    fn local_to_world_direction(&self, direction: Vec3) -> Vec3 {
+        quat_rotate_vec3(&self.orientation, &direction).normalize()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct EntityOptimizations {
     /// Don't render triangles whose normal is in the same direction as the camera is looking
     /// May cause some X-ray vision bugs...
@@ -59,8 +126,25 @@ pub struct EntityOptimizations {
     pub frustum_cutting: bool,
 }
 
+/// Makes an entity follow another one, like the rings of a planet.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ParentLink {
+    /// Index of the parent on `Model::entities`.
+    pub index: usize,
+    /// Scale of the entity relative to the parent.
+    pub scale: f32,
+    /// Radians per millisecond the entity spins around the Y axis of the parent.
+    #[serde(default)]
+    pub spin: f32,
+}
+
 pub struct Entity {
+    /// Finds the entity without depending on its index on `Model::entities`.
+    pub name: Option<String>,
     pub objs: Vec<Obj>,
+    /// Where the mesh of the entity comes from.
+    /// Entities without one can't be saved into a scene file.
+    pub mesh: Option<MeshSource>,
     pub shaders: Vec<EntityShader>,
     pub model_matrix: Mat4,
     pub optimizations: EntityOptimizations,
@@ -73,20 +157,53 @@ pub struct Entity {
     pub ellipsis: Option<Ellipsis>,
     /// Color of the wireframe of the model
     pub wireframe_color: Option<Color>,
+    /// How the entity reacts to light on `ShaderType::Lighting`.
+    pub material: Material,
+    /// Lights that move along with the entity, defined in model space.
+    pub lights: Vec<Light>,
+    /// The entity this one follows around, if any.
+    pub parent: Option<ParentLink>,
+    /// Transparent entities are rendered after the opaque ones,
+    /// blending their fragments by their alpha. They don't cast shadows.
+    pub transparent: bool,
 }
 
 impl Entity {
     pub fn modify_model(&mut self, new_model: EntityModel) {
-        let model_matrix =
-            create_model_matrix(new_model.translation, new_model.scale, new_model.rotation);
+        let model_matrix = create_model_matrix(
+            new_model.translation,
+            new_model.scale,
+            new_model.orientation,
+        );
         self.model = new_model;
         self.model_matrix = model_matrix;
     }
+
+    /// Moves the entity to where its parent is, spinning it on top of the parent orientation.
+    ///
+    /// * `parent`: The model of the parent.
+    /// * `time`: The current time, in milliseconds.
+    pub fn follow_parent(&mut self, parent: &EntityModel, time: f32) {
+        let Some(ParentLink { scale, spin, .. }) = self.parent else {
+            return;
+        };
+
+        self.modify_model(EntityModel {
+            translation: parent.translation,
+            scale: parent.scale * scale,
+            orientation: quat_normalize(
+                &(parent.orientation * quat_angle_axis(spin * time, &Vec3::y())),
+            ),
+        });
+    }
 }
 
 pub enum ViewType {
+    /// Camera orbiting around the entity selected in `Model::orbit_lock`.
     BirdEye,
     FirstPerson,
+    /// Camera detached from the ship, useful for debugging.
+    FreeFly,
 }
 
 pub struct Ship {
@@ -107,8 +224,102 @@ pub struct Model {
     pub uniforms: Uniforms,
     pub camera: Camera,
     pub textures: GameTextures,
+    /// The shaders used by `ShaderType::Custom` layers.
+    pub shader_registry: ShaderRegistry,
     pub lights: Vec<Light>,
+    /// Light that reaches every surface, even the ones facing away from all the lights.
+    pub ambient_light: AmbientLightIntensity,
     pub skybox: Skybox,
     pub previous_fpv_state: (Ship, Camera),
     pub game_window: GameWindow,
+    pub window_size: (usize, usize),
+    pub framebuffer_size: (usize, usize),
+    pub camera_path: CameraPath,
+    /// Some if the `camera_path` is currently being played.
+    pub path_playback: Option<PathPlayback>,
+    /// Controls the camera while on `ViewType::FirstPerson`.
+    pub chase_camera: ChaseCamera,
+    /// Distance the camera moves each time a movement key is pressed on `ViewType::FreeFly`.
+    pub free_fly_speed: f32,
+    /// The entity the camera orbits around while on `ViewType::BirdEye`.
+    pub orbit_lock: OrbitLock,
+    /// The last entity the user clicked on.
+    pub selected_entity: Option<PickResult>,
+    /// How the framebuffer is split between cameras.
+    pub viewport_layout: ViewportLayout,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_heading_follows_local_axes() {
+        let model = EntityModel {
+            orientation: Quat::identity(),
+            scale: 1.0,
+            translation: Vec3::zeros(),
+        };
+
+        // Pitching straight up and then yawing should turn around the nose,
+        // not around the world Y axis (which would be gimbal lock).
+        let model = EntityModel {
+            orientation: model.rotated_locally(vec3(-PI / 2.0, 0.0, 0.0)),
+            ..model
+        };
+        assert!(equal(model.forward().y, 1.0, 1e-5));
+
+        let model = EntityModel {
+            orientation: model.rotated_locally(vec3(0.0, PI / 2.0, 0.0)),
+            ..model
+        };
+        assert!(equal(model.forward().y, 0.0, 1e-5));
+        assert!(equal(model.forward().x, 1.0, 1e-5));
+    }
+
+    #[test]
+    fn test_children_follow_and_spin_around_parent() {
+        let parent = EntityModel {
+            orientation: Quat::identity(),
+            scale: 2.0,
+            translation: vec3(10.0, 0.0, 5.0),
+        };
+        let mut clouds = Entity {
+            name: None,
+            objs: vec![],
+            mesh: None,
+            shaders: vec![],
+            model_matrix: Mat4::identity(),
+            optimizations: EntityOptimizations {
+                camera_direction: false,
+                frustum_cutting: false,
+            },
+            use_screen_position: false,
+            model: EntityModel {
+                orientation: Quat::identity(),
+                scale: 1.0,
+                translation: Vec3::zeros(),
+            },
+            custom_depth: None,
+            ellipsis: None,
+            wireframe_color: None,
+            material: Material::default(),
+            lights: vec![],
+            parent: Some(ParentLink {
+                index: 0,
+                scale: 1.1,
+                spin: PI / 2000.0,
+            }),
+            transparent: true,
+        };
+
+        // After a second the clouds did a quarter turn.
+        clouds.follow_parent(&parent, 1000.0);
+
+        assert_eq!(clouds.model.translation, parent.translation);
+        assert!(equal(clouds.model.scale, 2.2, 1e-5));
+        assert!(equal(clouds.model.forward().x, 1.0, 1e-5));
+    }
 }
@@ -1,11 +1,188 @@
-use crate::color::Color;
-use nalgebra_glm::Vec3;
+use crate::{color::Color, Entity};
+use nalgebra_glm::{dot, quat_rotate_vec3, smoothstep, Vec3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+/// How fast the light of a source fades with the distance.
+/// The light that reaches a point is divided by `constant + linear * d + quadratic * d^2`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    /// The light doesn't fade at all.
+    pub const NONE: Attenuation = Attenuation {
+        constant: 1.0,
+        linear: 0.0,
+        quadratic: 0.0,
+    };
+
+    /// Computes the fraction of light that reaches a point at the given distance.
+    pub fn factor(&self, distance: f32) -> f32 {
+        let Attenuation {
+            constant,
+            linear,
+            quadratic,
+        } = self;
+
+        1.0 / (constant + linear * distance + quadratic * distance * distance).max(f32::EPSILON)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LightKind {
+    /// A light infinitely far away, all of its rays are parallel.
+    Directional {
+        /// The direction the light travels in.
+        direction: Vec3,
+    },
+    /// A light that shines in every direction from its position.
+    Point { attenuation: Attenuation },
+    /// A light that shines in a cone from its position.
+    Spot {
+        /// The direction the cone points to.
+        direction: Vec3,
+        /// Angle in radians from the center of the cone where the light starts to fade.
+        inner_angle: f32,
+        /// Angle in radians from the center of the cone where there's no more light.
+        outer_angle: f32,
+        attenuation: Attenuation,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Light {
+    pub kind: LightKind,
+    /// Ignored by `LightKind::Directional` lights.
     pub position: Vec3,
     pub color: Color,
     pub intensity: f32,
+    /// Size of the light source, bigger lights cast softer shadows.
+    /// Zero makes the shadows completely hard.
+    pub radius: f32,
+    /// If true a `ShadowMap` gets rendered for this light every frame.
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    /// Computes how the light reaches the given point.
+    /// Returns the normalized direction from the point towards the light
+    /// and the intensity of the light on that point.
+    pub fn illuminate(&self, point: &Vec3) -> (Vec3, f32) {
+        match self.kind {
+            LightKind::Directional { direction } => (-direction.normalize(), self.intensity),
+            LightKind::Point { attenuation } => {
+                let to_light = self.position - point;
+                let distance = to_light.magnitude();
+
+                (
+                    to_light / distance,
+                    self.intensity * attenuation.factor(distance),
+                )
+            }
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                let to_light = self.position - point;
+                let distance = to_light.magnitude();
+                let light_dir = to_light / distance;
+
+                let cos_angle = dot(&-light_dir, &direction.normalize());
+                let cone = smoothstep(outer_angle.cos(), inner_angle.cos(), cos_angle);
+
+                (
+                    light_dir,
+                    self.intensity * attenuation.factor(distance) * cone,
+                )
+            }
+        }
+    }
+
+    /// Computes the distance from the point to the light.
+    /// Directional lights are infinitely far away.
+    pub fn distance_to(&self, point: &Vec3) -> f32 {
+        match self.kind {
+            LightKind::Directional { .. } => f32::INFINITY,
+            _ => (self.position - point).magnitude(),
+        }
+    }
+
+    /// Moves a light defined in the model space of the entity into world space.
+    pub fn attached_to(&self, entity: &Entity) -> Self {
+        let to_world = |direction: Vec3| quat_rotate_vec3(&entity.model.orientation, &direction);
+        let kind = match self.kind {
+            LightKind::Directional { direction } => LightKind::Directional {
+                direction: to_world(direction),
+            },
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => LightKind::Spot {
+                direction: to_world(direction),
+                inner_angle,
+                outer_angle,
+                attenuation,
+            },
+            kind => kind,
+        };
+
+        Light {
+            kind,
+            position: (entity.model_matrix * self.position.push(1.0)).xyz(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Entity {
+    /// The lights attached to this entity, in world space.
+    pub fn world_lights(&self) -> impl Iterator<Item = Light> + '_ {
+        self.lights.iter().map(|light| light.attached_to(self))
+    }
 }
 
 pub type AmbientLightIntensity = f32;
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use nalgebra_glm::vec3;
+
+    use super::*;
+    use crate::equal;
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = Light {
+            kind: LightKind::Spot {
+                direction: vec3(0.0, 0.0, -1.0),
+                inner_angle: PI / 8.0,
+                outer_angle: PI / 4.0,
+                attenuation: Attenuation::NONE,
+            },
+            position: Vec3::zeros(),
+            color: Color::white(),
+            intensity: 1.0,
+            radius: 0.0,
+            casts_shadows: false,
+        };
+
+        let (direction, inside) = light.illuminate(&vec3(0.0, 0.0, -10.0));
+        assert!(equal(direction.z, 1.0, 1e-5));
+        assert!(equal(inside, 1.0, 1e-5));
+
+        let (_, outside) = light.illuminate(&vec3(10.0, 0.0, -1.0));
+        assert!(equal(outside, 0.0, 1e-5));
+
+        let (_, behind) = light.illuminate(&vec3(0.0, 0.0, 10.0));
+        assert!(equal(behind, 0.0, 1e-5));
+    }
+}
@@ -1,25 +1,36 @@
 use fastnoise_lite::FastNoiseLite;
-use gothspace::camera::Camera;
+use gothspace::camera::chase::ChaseCamera;
+use gothspace::camera::path::{
+    Anchor, CameraKeyframe, CameraPath, Easing, Interpolation, PathPlayback,
+};
+use gothspace::camera::target::OrbitLock;
+use gothspace::camera::{Camera, DEFAULT_FOV};
 use gothspace::color::Color;
+use gothspace::fragment::bake::{bake_entity, BakeSettings};
 use gothspace::fragment::ellipsis::next_point_in_ellipsis;
 use gothspace::fragment::planets::{
-    create_disco_planet, create_face_planet, create_gas_giant, create_green_planet,
-    create_ocean_planet, create_snow_planet, create_sun,
+    create_cloud_layer, create_disco_planet, create_face_planet, create_gas_giant,
+    create_gas_giant_rings, create_green_planet, create_ocean_planet, create_snow_planet,
+    create_sun, GREEN_CLOUDS_NOISE_OFFSET, OCEAN_CLOUDS_NOISE_OFFSET,
 };
-use gothspace::fragment::ship::{create_ship, create_ship_from, ORIGINAL_ROTATION};
-use gothspace::light::Light;
+use gothspace::fragment::shaders::ShaderRegistry;
+use gothspace::fragment::ship::{create_ship, create_ship_from};
+use gothspace::light::{Attenuation, Light, LightKind};
+use gothspace::picking::pick;
 use gothspace::render::render;
+use gothspace::scene::{Scene, SceneDescription};
 use gothspace::skybox::Skybox;
 use gothspace::texture::{GameTextures, Texture};
 use gothspace::vertex::shader::{
-    create_projection_matrix, create_view_matrix, create_viewport_matrix, Uniforms,
+    create_projection_matrix, create_view_matrix, create_viewport_matrix, ShaderType, Uniforms,
 };
-use gothspace::{framebuffer, EntityModel, GameWindow};
+use gothspace::viewport::ViewportLayout;
+use gothspace::{framebuffer, Entity, EntityModel, GameWindow};
 use gothspace::{Message, Model};
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
 use mouse_rs::types::Point;
 use mouse_rs::Mouse;
-use nalgebra_glm::{vec3, Vec3};
+use nalgebra_glm::{vec2, vec3, Mat4, Vec3};
 use std::collections::VecDeque;
 use std::f32::consts::PI;
 use std::time::{Duration, Instant};
@@ -32,6 +43,17 @@ const PLAYER_ACCELERATION: f32 = 1e-3;
 const MAX_PLAYER_SPEED: f32 = 0.3;
 const CAM_POS_DELTA_TO_SHIP: Vec3 = Vec3::new(0.0, 1.0, 10.0);
 const CAM_CENTER_DELTA_TO_SHIP: Vec3 = Vec3::new(0.0, 1.5, 0.0);
+const CAM_LOOK_AHEAD: f32 = 5.0;
+const BIRD_EYE_OFFSET: Vec3 = Vec3::new(0.0, 130.0, 0.0);
+const TARGET_TRANSITION_DURATION: f32 = 1500.0;
+const FREE_FLY_SPEED: f32 = 0.5;
+const FREE_FLY_SPEED_FACTOR: f32 = 1.1;
+const MIN_FREE_FLY_SPEED: f32 = 0.05;
+const MAX_FREE_FLY_SPEED: f32 = 20.0;
+const OFFLINE_FRAMERATE: f32 = 30.0;
+const AMBIENT_LIGHT: f32 = 0.08;
+const DEFAULT_SCENE_FILE: &str = "assets/scenes/solar_system.ron";
+const BAKE_SIZE: (u32, u32) = (1024, 512);
 
 fn main() {
     let window_width = 1080;
@@ -44,6 +66,54 @@ fn main() {
 
     println!("Framebuffer: ({framebuffer_width}, {framebuffer_height})");
 
+    let args: Vec<String> = std::env::args().collect();
+    let arg_value = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .map(|idx| args.get(idx + 1).map(String::as_str))
+    };
+
+    if let Some(output_path) = arg_value("--save-scene") {
+        let output_path = output_path.unwrap_or(DEFAULT_SCENE_FILE);
+        let data = init(
+            (window_width, window_height),
+            (framebuffer_width, framebuffer_height),
+            create_builtin_scene(),
+        );
+        SceneDescription::from_model(&data)
+            .save(output_path)
+            .expect("Couldn't save the scene!");
+        println!("Saved the scene to {output_path}");
+        return;
+    }
+
+    let scene = match arg_value("--scene") {
+        Some(scene_path) => {
+            let scene_path = scene_path.unwrap_or(DEFAULT_SCENE_FILE);
+            println!("Loading scene from {scene_path}...");
+            SceneDescription::load(scene_path)
+                .and_then(SceneDescription::build)
+                .unwrap_or_else(|err| panic!("{err}"))
+        }
+        None => create_builtin_scene(),
+    };
+
+    // Bakes the static shader layers, saving the textures if there's a directory after the flag.
+    let bake =
+        arg_value("--bake").map(|output_dir| output_dir.filter(|dir| !dir.starts_with("--")));
+
+    if let Some(output_dir) = arg_value("--render-path") {
+        let output_dir = output_dir.unwrap_or("frames");
+        render_camera_path(
+            output_dir,
+            (window_width, window_height),
+            (framebuffer_width, framebuffer_height),
+            scene,
+            bake,
+        );
+        return;
+    }
+
     let mut framebuffer = framebuffer::Framebuffer::new(framebuffer_width, framebuffer_height);
     // framebuffer.set_background_color(0x333355);
 
@@ -88,9 +158,12 @@ fn main() {
     let mut data = init(
         (window_width, window_height),
         (framebuffer_width, framebuffer_height),
+        scene,
     );
-    let mut noise = FastNoiseLite::with_seed(1506);
-    noise.set_frequency(Some(0.004));
+    let mut noise = create_render_noise();
+    if let Some(output_dir) = bake {
+        bake_entities(&mut data, &mut noise, output_dir);
+    }
     render(&mut framebuffer, &data, &mut noise);
     framebuffer.set_background_from_texture(&Texture::new("assets/textures/instructions.jpg"));
 
@@ -106,6 +179,7 @@ fn main() {
     let last_recorded_frames_max_count = 60;
     let mut last_recorded_frames = VecDeque::with_capacity(last_recorded_frames_max_count);
     let mut time = 0.0;
+    let mut was_mouse_down = false;
     while window.is_open() {
         let mut should_update = false;
 
@@ -118,10 +192,22 @@ fn main() {
             break;
         }
 
+        let free_fly = matches!(data.view_type, gothspace::ViewType::FreeFly);
         let mut messages: Vec<Message> = window
             .get_keys_pressed(KeyRepeat::Yes)
             .into_iter()
             .filter_map(|key| match key {
+                Key::W if free_fly => Some(Message::MoveFreeCamera(vec3(0.0, 0.0, 1.0))),
+                Key::S if free_fly => Some(Message::MoveFreeCamera(vec3(0.0, 0.0, -1.0))),
+                Key::D if free_fly => Some(Message::MoveFreeCamera(vec3(1.0, 0.0, 0.0))),
+                Key::A if free_fly => Some(Message::MoveFreeCamera(vec3(-1.0, 0.0, 0.0))),
+                Key::E if free_fly => Some(Message::MoveFreeCamera(vec3(0.0, 1.0, 0.0))),
+                Key::Q if free_fly => Some(Message::MoveFreeCamera(vec3(0.0, -1.0, 0.0))),
+                Key::Equal if free_fly => Some(Message::ChangeFreeFlySpeed(FREE_FLY_SPEED_FACTOR)),
+                Key::Minus if free_fly => {
+                    Some(Message::ChangeFreeFlySpeed(1.0 / FREE_FLY_SPEED_FACTOR))
+                }
+
                 Key::W => Some(Message::Accelerate(PLAYER_ACCELERATION)),
                 Key::S => Some(Message::Accelerate(-PLAYER_ACCELERATION)),
 
@@ -137,6 +223,12 @@ fn main() {
                 Key::Right => Some(Message::RotateShip(
                     vec3(0.0, -1.0, 0.0) * SHIP_ROTATION_SPEED,
                 )),
+                Key::Q => Some(Message::RotateShip(
+                    vec3(0.0, 0.0, -1.0) * SHIP_ROTATION_SPEED,
+                )),
+                Key::E => Some(Message::RotateShip(
+                    vec3(0.0, 0.0, 1.0) * SHIP_ROTATION_SPEED,
+                )),
 
                 Key::Tab => {
                     if mode_cooldown_timer == 0 {
@@ -173,6 +265,47 @@ fn main() {
 
                 Key::Space => Some(Message::StopShip),
 
+                Key::F => {
+                    if mode_cooldown_timer == 0 {
+                        mode_cooldown_timer = mode_cooldown;
+                        if free_fly {
+                            Some(Message::SnapToShip)
+                        } else {
+                            Some(Message::FreeFlyView)
+                        }
+                    } else {
+                        None
+                    }
+                }
+
+                Key::LeftBracket | Key::RightBracket => {
+                    if mode_cooldown_timer == 0 {
+                        mode_cooldown_timer = mode_cooldown;
+                        let step = if key == Key::LeftBracket { -1 } else { 1 };
+                        Some(Message::CycleTarget(step))
+                    } else {
+                        None
+                    }
+                }
+
+                Key::P => {
+                    if mode_cooldown_timer == 0 {
+                        mode_cooldown_timer = mode_cooldown;
+                        Some(Message::ToggleCameraPath)
+                    } else {
+                        None
+                    }
+                }
+
+                Key::V => {
+                    if mode_cooldown_timer == 0 {
+                        mode_cooldown_timer = mode_cooldown;
+                        Some(Message::CycleViewportLayout)
+                    } else {
+                        None
+                    }
+                }
+
                 _ => None,
             })
             .collect();
@@ -188,6 +321,18 @@ fn main() {
         ));
         previous_mouse_pos = Point { x, y };
 
+        let mouse_down = window.get_mouse_down(MouseButton::Left);
+        if mouse_down && !was_mouse_down {
+            if let Some((mouse_x, mouse_y)) = window.get_mouse_pos(MouseMode::Discard) {
+                let (current_width, current_height) = window_size;
+                messages.push(Message::PickEntity(vec2(
+                    mouse_x * framebuffer_width as f32 / current_width as f32,
+                    mouse_y * framebuffer_height as f32 / current_height as f32,
+                )));
+            }
+        }
+        was_mouse_down = mouse_down;
+
         if let Some(delta) = window.get_scroll_wheel().map(|(_, y)| y) {
             messages.push(Message::ZoomCamera(delta * ZOOM_SPEED));
         }
@@ -252,8 +397,51 @@ fn main() {
     }
 }
 
+/// The solar system that's shown when no scene file is given.
+fn create_builtin_scene() -> Scene {
+    // Children follow their parents by index, so those are taken as the parents get pushed.
+    let mut entities = vec![create_sun(vec3(0.0, 0.0, 0.0))];
+    let green_planet = entities.len();
+    entities.push(create_green_planet());
+    entities.push(create_disco_planet());
+    let gas_planet = entities.len();
+    entities.push(create_gas_giant());
+    entities.push(create_face_planet());
+    entities.push(create_snow_planet());
+    let ocean_planet = entities.len();
+    entities.push(create_ocean_planet());
+    entities.extend([
+        create_gas_giant_rings(gas_planet),
+        create_cloud_layer(green_planet, 1e-4, GREEN_CLOUDS_NOISE_OFFSET),
+        create_cloud_layer(ocean_planet, 1.5e-4, OCEAN_CLOUDS_NOISE_OFFSET),
+    ]);
+
+    let lights = vec![Light {
+        kind: LightKind::Point {
+            attenuation: Attenuation::NONE,
+        },
+        position: Vec3::zeros(),
+        color: Color::white(),
+        intensity: 1.0,
+        // Same as the sun entity.
+        radius: 4.0,
+        casts_shadows: true,
+    }];
+
+    Scene {
+        entities,
+        lights,
+        ambient_light: AMBIENT_LIGHT,
+        skybox: Skybox::new(5000, 50.0),
+    }
+}
+
 /// Init the default state
-fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize)) -> Model {
+fn init(
+    window_dimensions: (usize, usize),
+    framebuffer_dimensions: (usize, usize),
+    scene: Scene,
+) -> Model {
     let (framebuffer_width, framebuffer_height) = framebuffer_dimensions;
     let (window_width, window_height) = window_dimensions;
 
@@ -265,50 +453,54 @@ fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize
         Vec3::new(0.0, 1.0, 0.0),
         MAX_ZOOM,
     );
-    let sun = create_sun(vec3(0.0, 0.0, 0.0));
-    let green_planet = create_green_planet();
-    let disco_planet = create_disco_planet();
-    let gas_planet = create_gas_giant();
-    let face_planet = create_face_planet();
-    let snow_planet = create_snow_planet();
-    let ocean_planet = create_ocean_planet();
-    let entities = vec![
-        sun,
-        green_planet,
-        disco_planet,
-        gas_planet,
-        face_planet,
-        snow_planet,
-        ocean_planet,
-    ];
+    let mut chase_camera = ChaseCamera::new(CAM_POS_DELTA_TO_SHIP, CAM_CENTER_DELTA_TO_SHIP);
+    chase_camera.look_ahead = CAM_LOOK_AHEAD;
+
+    let Scene {
+        entities,
+        lights,
+        ambient_light,
+        skybox,
+    } = scene;
 
     let view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
     println!("View Matrix: {:#?}", view_matrix);
-    let projection_matrix = create_projection_matrix(window_width as f32, window_height as f32);
+    let projection_matrix =
+        create_projection_matrix(window_width as f32, window_height as f32, camera.fov);
     println!("Projection Matrix: {:#?}", projection_matrix);
     let viewport_matrix =
         create_viewport_matrix(framebuffer_width as f32, framebuffer_height as f32);
     println!("Viewport matrix: {:#?}", viewport_matrix);
 
-    let lights = vec![Light {
-        position: Vec3::zeros(),
-        color: Color::white(),
-        intensity: 1.0,
-    }];
-
-    let skybox = Skybox::new(5000, 50.0);
-    let textures = GameTextures::new("assets/textures/");
+    let mut textures = GameTextures::new("assets/textures/");
+    // The GIFs are decoded once, before the game starts.
+    for (shader_type, _, _) in entities
+        .iter()
+        .chain(std::iter::once(&ship.entity))
+        .flat_map(|entity| &entity.shaders)
+    {
+        if let ShaderType::AnimatedTexture { path, .. } = shader_type {
+            // The layer gets skipped if its GIF isn't loaded.
+            if let Err(err) = textures.load_animated(path) {
+                println!("Couldn't load the GIF {path}: {err}");
+            }
+        }
+    }
 
     let game_window = GameWindow::Controls;
+    let camera_path = create_tour_path(&entities);
 
     Model {
         game_window,
         view_type: gothspace::ViewType::FirstPerson,
         textures,
+        shader_registry: ShaderRegistry::with_builtins(),
         entities,
         previous_fpv_state: (create_ship_from(&ship), camera),
         ship,
         uniforms: Uniforms {
+            camera_position: camera.eye,
+            model_matrix: Mat4::identity(),
             view_matrix,
             projection_matrix,
             viewport_matrix,
@@ -316,12 +508,148 @@ fn init(window_dimensions: (usize, usize), framebuffer_dimensions: (usize, usize
         },
         camera,
         lights,
+        ambient_light,
         skybox,
+        window_size: window_dimensions,
+        framebuffer_size: framebuffer_dimensions,
+        camera_path,
+        path_playback: None,
+        chase_camera,
+        free_fly_speed: FREE_FLY_SPEED,
+        orbit_lock: OrbitLock::new(0, TARGET_TRANSITION_DURATION),
+        selected_entity: None,
+        viewport_layout: ViewportLayout::Single,
+    }
+}
+
+/// A tour through the solar system that ends looking at the sun from above.
+fn create_tour_path(entities: &[Entity]) -> CameraPath {
+    let up = vec3(0.0, 1.0, 0.0);
+    let sun = Anchor::Point(Vec3::zeros());
+    let green_planet = |offset| Anchor::entity_named(entities, "green_planet", offset);
+    let gas_giant = |offset| Anchor::entity_named(entities, "gas_giant", offset);
+    let keyframes = vec![
+        CameraKeyframe {
+            time: 0.0,
+            eye: Anchor::Point(vec3(0.0, 40.0, 120.0)),
+            center: sun,
+            up,
+            fov: DEFAULT_FOV,
+            easing: Easing::EaseInOut,
+        },
+        CameraKeyframe {
+            time: 6000.0,
+            eye: green_planet(vec3(0.0, 3.0, 12.0)),
+            center: green_planet(Vec3::zeros()),
+            up,
+            fov: DEFAULT_FOV * 0.8,
+            easing: Easing::Linear,
+        },
+        CameraKeyframe {
+            time: 10000.0,
+            eye: green_planet(vec3(8.0, 2.0, -8.0)),
+            center: green_planet(Vec3::zeros()),
+            up,
+            fov: DEFAULT_FOV * 0.8,
+            easing: Easing::EaseInOut,
+        },
+        CameraKeyframe {
+            time: 16000.0,
+            eye: gas_giant(vec3(0.0, 6.0, 15.0)),
+            center: gas_giant(Vec3::zeros()),
+            up,
+            fov: DEFAULT_FOV,
+            easing: Easing::EaseInOut,
+        },
+        CameraKeyframe {
+            time: 22000.0,
+            eye: Anchor::Point(vec3(0.0, 150.0, 1.0)),
+            center: sun,
+            up,
+            fov: DEFAULT_FOV * 1.2,
+            easing: Easing::Linear,
+        },
+    ];
+
+    CameraPath::new(keyframes, Interpolation::CatmullRom, false)
+}
+
+fn create_render_noise() -> FastNoiseLite {
+    let mut noise = FastNoiseLite::with_seed(1506);
+    noise.set_frequency(Some(0.004));
+    noise
+}
+
+/// Bakes the layers of the entities that don't change over time into textures.
+/// If there's an `output_dir` the textures are saved there too.
+fn bake_entities(data: &mut Model, noise: &mut FastNoiseLite, output_dir: Option<&str>) {
+    let Model {
+        entities,
+        textures,
+        shader_registry,
+        ..
+    } = data;
+
+    if let Some(output_dir) = output_dir {
+        std::fs::create_dir_all(output_dir).expect("Couldn't create the bake directory!");
+    }
+
+    for (idx, entity) in entities.iter_mut().enumerate() {
+        let settings = BakeSettings {
+            width: BAKE_SIZE.0,
+            height: BAKE_SIZE.1,
+            output_path: output_dir.map(|dir| format!("{dir}/entity_{idx}.png")),
+        };
+        match bake_entity(entity, &settings, shader_registry, textures, noise) {
+            Ok(Some(_)) => println!("Baked entity {idx}"),
+            Ok(None) => {}
+            Err(err) => println!("Couldn't save the bake of entity {idx}: {err}"),
+        }
+    }
+}
+
+/// Plays the camera path without opening a window,
+/// saving every frame as a .bmp inside `output_dir`.
+fn render_camera_path(
+    output_dir: &str,
+    window_dimensions: (usize, usize),
+    framebuffer_dimensions: (usize, usize),
+    scene: Scene,
+    bake: Option<Option<&str>>,
+) {
+    let (framebuffer_width, framebuffer_height) = framebuffer_dimensions;
+    let mut framebuffer = framebuffer::Framebuffer::new(framebuffer_width, framebuffer_height);
+    let mut noise = create_render_noise();
+
+    std::fs::create_dir_all(output_dir).expect("Couldn't create the output directory!");
+
+    let mut data = init(window_dimensions, framebuffer_dimensions, scene);
+    if let Some(bake_dir) = bake {
+        bake_entities(&mut data, &mut noise, bake_dir);
+    }
+    data = update(data, Message::StartGame);
+    data = update(data, Message::ToggleCameraPath);
+
+    let frame_time = 1000.0 / OFFLINE_FRAMERATE;
+    let frame_count = (data.camera_path.duration() / frame_time).ceil() as usize;
+    for frame in 0..=frame_count {
+        data = update(data, Message::UpdateTime(frame as f32 * frame_time));
+
+        framebuffer.clear();
+        render(&mut framebuffer, &data, &mut noise);
+
+        let file_path = format!("{output_dir}/frame_{frame:04}.bmp");
+        framebuffer
+            .save(&file_path)
+            .expect("Couldn't save the frame!");
+        println!("Saved frame {frame}/{frame_count}");
     }
 }
 
 fn update(data: Model, msg: Message) -> Model {
     match msg {
+        Message::RotateCamera(..) | Message::ZoomCamera(..) if data.path_playback.is_some() => data,
+
         Message::RotateCamera(delta_yaw, delta_pitch) => {
             let Model {
                 mut camera,
@@ -329,9 +657,15 @@ fn update(data: Model, msg: Message) -> Model {
                 ..
             } = data;
 
-            camera.orbit(delta_yaw, delta_pitch);
+            match data.view_type {
+                gothspace::ViewType::FreeFly => {
+                    camera.move_center(vec3(-delta_yaw, delta_pitch, 0.0), 1.0)
+                }
+                _ => camera.orbit(delta_yaw, delta_pitch),
+            }
 
             let uniforms = Uniforms {
+                camera_position: camera.eye,
                 view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
                 ..uniforms
             };
@@ -351,13 +685,7 @@ fn update(data: Model, msg: Message) -> Model {
                 ..
             } = data;
 
-            let ship_rotation = ship.entity.model.rotation - ORIGINAL_ROTATION;
-            let ship_direction = vec3(
-                -ship_rotation.y.sin(),
-                -ship_rotation.x.sin(),
-                -ship_rotation.y.cos(),
-            )
-            .normalize();
+            let ship_direction = ship.entity.model.forward();
 
             ship.acceleration += ship_direction * delta;
             ship.velocity += ship.acceleration;
@@ -379,8 +707,12 @@ fn update(data: Model, msg: Message) -> Model {
                 mut entities,
                 mut ship,
                 mut camera,
+                mut path_playback,
+                mut chase_camera,
+                window_size: (window_width, window_height),
                 ..
             } = data;
+            let delta_time = time - uniforms.time;
             let uniforms = Uniforms { time, ..uniforms };
 
             for entity in &mut entities {
@@ -393,6 +725,14 @@ fn update(data: Model, msg: Message) -> Model {
                 }
             }
 
+            // The orbits are already updated, so children end up where their parents are now.
+            for index in 0..entities.len() {
+                if let Some(link) = entities[index].parent {
+                    let parent = entities[link.index].model.clone();
+                    entities[index].follow_parent(&parent, time);
+                }
+            }
+
             let previous_position = ship.entity.model.translation;
             ship.velocity += ship.acceleration;
             if ship.velocity.magnitude() > MAX_PLAYER_SPEED {
@@ -404,9 +744,33 @@ fn update(data: Model, msg: Message) -> Model {
                 ..ship.entity.model
             });
 
-            camera.modify_center_and_eye(translation, camera.eye + ship.velocity);
+            let pose = path_playback.map(|p| p.pose_at(&data.camera_path, time, &entities));
+            match (path_playback, pose) {
+                (_, Some(Some(pose))) => camera.apply_pose(&pose),
+                (Some(playback), Some(None)) => {
+                    // The path ended, going back to where we were...
+                    camera = playback.previous_camera;
+                    path_playback = None;
+                }
+                _ => match data.view_type {
+                    gothspace::ViewType::FirstPerson => {
+                        chase_camera.update(&mut camera, &ship, delta_time)
+                    }
+                    gothspace::ViewType::BirdEye => {
+                        data.orbit_lock.update(&mut camera, &entities, time)
+                    }
+                    gothspace::ViewType::FreeFly => {}
+                },
+            }
+
             let uniforms = Uniforms {
+                camera_position: camera.eye,
                 view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                projection_matrix: create_projection_matrix(
+                    window_width as f32,
+                    window_height as f32,
+                    camera.fov,
+                ),
                 ..uniforms
             };
 
@@ -415,6 +779,8 @@ fn update(data: Model, msg: Message) -> Model {
                 entities,
                 camera,
                 ship,
+                path_playback,
+                chase_camera,
                 ..data
             }
         }
@@ -426,15 +792,23 @@ fn update(data: Model, msg: Message) -> Model {
         }
 
         Message::ResizeWindow(new_size) => {
-            let Model { uniforms, .. } = data;
+            let Model {
+                uniforms, camera, ..
+            } = data;
 
-            let projection_matrix = create_projection_matrix(new_size.0 as f32, new_size.1 as f32);
+            let projection_matrix =
+                create_projection_matrix(new_size.0 as f32, new_size.1 as f32, camera.fov);
             let uniforms = Uniforms {
                 projection_matrix,
                 ..uniforms
             };
 
-            Model { uniforms, ..data }
+            Model {
+                uniforms,
+                camera,
+                window_size: new_size,
+                ..data
+            }
         }
 
         Message::AlternateView => {
@@ -447,7 +821,7 @@ fn update(data: Model, msg: Message) -> Model {
             } = data;
 
             match view_type {
-                gothspace::ViewType::BirdEye => {
+                gothspace::ViewType::BirdEye | gothspace::ViewType::FreeFly => {
                     let ship = create_ship_from(&previous_fpv_state.0);
                     let camera = previous_fpv_state.1;
 
@@ -463,8 +837,13 @@ fn update(data: Model, msg: Message) -> Model {
                     // Saving view state to know when to return...
                     let previous_fpv_state = (create_ship_from(&ship), camera);
 
-                    camera.eye = vec3(0.0, 130.0, 0.0);
-                    camera.center = Vec3::zeros();
+                    let target_position = data
+                        .entities
+                        .get(data.orbit_lock.target)
+                        .map(|e| e.model.translation)
+                        .unwrap_or(Vec3::zeros());
+                    camera.eye = target_position + BIRD_EYE_OFFSET;
+                    camera.center = target_position;
 
                     Model {
                         ship,
@@ -490,7 +869,7 @@ fn update(data: Model, msg: Message) -> Model {
             let Model { mut ship, .. } = data;
 
             ship.entity.modify_model(EntityModel {
-                rotation: ship.entity.model.rotation + rotation,
+                orientation: ship.entity.model.rotated_locally(rotation),
                 ..ship.entity.model
             });
 
@@ -512,6 +891,191 @@ fn update(data: Model, msg: Message) -> Model {
                 ..data
             }
         }
+
+        Message::FreeFlyView => {
+            let Model {
+                ship,
+                camera,
+                view_type,
+                previous_fpv_state,
+                ..
+            } = data;
+
+            // Only save the state when coming from the ship,
+            // the other views already saved it.
+            let previous_fpv_state = match view_type {
+                gothspace::ViewType::FirstPerson => (create_ship_from(&ship), camera),
+                _ => previous_fpv_state,
+            };
+
+            Model {
+                ship,
+                camera,
+                previous_fpv_state,
+                view_type: gothspace::ViewType::FreeFly,
+                ..data
+            }
+        }
+
+        Message::MoveFreeCamera(direction) => {
+            let Model {
+                mut camera,
+                free_fly_speed,
+                ..
+            } = data;
+
+            let delta = direction * free_fly_speed;
+            camera.strafe(delta.x);
+            camera.elevate(delta.y);
+            camera.advance_camera(delta.z);
+
+            Model {
+                camera,
+                free_fly_speed,
+                ..data
+            }
+        }
+
+        Message::ChangeFreeFlySpeed(factor) => {
+            let free_fly_speed =
+                (data.free_fly_speed * factor).clamp(MIN_FREE_FLY_SPEED, MAX_FREE_FLY_SPEED);
+
+            Model {
+                free_fly_speed,
+                ..data
+            }
+        }
+
+        Message::SnapToShip => {
+            let Model {
+                previous_fpv_state, ..
+            } = data;
+
+            let ship = create_ship_from(&previous_fpv_state.0);
+            let camera = previous_fpv_state.1;
+
+            Model {
+                ship,
+                camera,
+                previous_fpv_state,
+                view_type: gothspace::ViewType::FirstPerson,
+                ..data
+            }
+        }
+
+        Message::CycleTarget(step) => {
+            let Model {
+                mut orbit_lock,
+                camera,
+                uniforms,
+                ..
+            } = data;
+
+            orbit_lock.cycle(step, data.entities.len(), &camera, uniforms.time);
+            println!("Orbiting around entity {}", orbit_lock.target);
+
+            Model {
+                orbit_lock,
+                camera,
+                uniforms,
+                ..data
+            }
+        }
+
+        Message::PickEntity(screen_position) => {
+            let (framebuffer_width, framebuffer_height) = data.framebuffer_size;
+
+            // Insets are drawn on top, so the last viewport that contains the point wins.
+            let viewport = data
+                .viewport_layout
+                .viewports(framebuffer_width, framebuffer_height)
+                .into_iter()
+                .rev()
+                .find(|v| v.rect.contains(screen_position.x, screen_position.y));
+            let selected_entity = viewport.and_then(|viewport| {
+                let (_, viewport_uniforms) =
+                    viewport.camera_and_uniforms(&data, data.framebuffer_size);
+                pick(screen_position, &viewport_uniforms, &data.entities)
+            });
+
+            let Model {
+                mut orbit_lock,
+                camera,
+                uniforms,
+                ..
+            } = data;
+            match selected_entity {
+                Some(result) => {
+                    println!(
+                        "Selected entity {} at {:?}, {:.2} units away",
+                        result.entity, result.point, result.distance
+                    );
+                    orbit_lock.retarget(result.entity, &camera, uniforms.time);
+                }
+                None => println!("Nothing selected"),
+            }
+
+            Model {
+                orbit_lock,
+                camera,
+                uniforms,
+                selected_entity,
+                ..data
+            }
+        }
+
+        Message::CycleViewportLayout => {
+            let viewport_layout = data.viewport_layout.next();
+            println!("Using {:?} viewport layout", viewport_layout);
+
+            Model {
+                viewport_layout,
+                ..data
+            }
+        }
+
+        Message::ToggleCameraPath => {
+            let Model {
+                uniforms,
+                camera,
+                path_playback,
+                window_size: (window_width, window_height),
+                ..
+            } = data;
+
+            match path_playback {
+                Some(playback) => {
+                    let camera = playback.previous_camera;
+                    let uniforms = Uniforms {
+                        camera_position: camera.eye,
+                        view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+                        projection_matrix: create_projection_matrix(
+                            window_width as f32,
+                            window_height as f32,
+                            camera.fov,
+                        ),
+                        ..uniforms
+                    };
+
+                    Model {
+                        uniforms,
+                        camera,
+                        path_playback: None,
+                        ..data
+                    }
+                }
+                None => {
+                    let path_playback = Some(PathPlayback::new(uniforms.time, camera));
+
+                    Model {
+                        uniforms,
+                        camera,
+                        path_playback,
+                        ..data
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -1,10 +1,48 @@
-use nalgebra_glm::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+use nalgebra_glm::{quat_angle_axis, quat_rotate_vec3, vec2, vec3, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
 use tobj;
 
 use crate::vertex::Vertex;
 
 pub type Obj = Vec<Vertex>;
 
+/// Where the mesh of an entity comes from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MeshSource {
+    /// Path of an .obj file.
+    Obj(String),
+    /// A flat ring generated by `create_ring`.
+    Ring {
+        inner_radius: f32,
+        outer_radius: f32,
+        /// Radians the ring is rotated around the X axis.
+        tilt: f32,
+        segments: usize,
+    },
+}
+
+impl MeshSource {
+    /// Loads or generates the mesh.
+    pub fn load(&self) -> Result<Vec<Obj>, tobj::LoadError> {
+        match self {
+            MeshSource::Obj(file_path) => load_objs(file_path),
+            MeshSource::Ring {
+                inner_radius,
+                outer_radius,
+                tilt,
+                segments,
+            } => Ok(vec![create_ring(
+                *inner_radius,
+                *outer_radius,
+                *tilt,
+                *segments,
+            )]),
+        }
+    }
+}
+
 pub fn load_objs(filename: &str) -> Result<Vec<Obj>, tobj::LoadError> {
     let (models, _) = tobj::load_obj(
         filename,
@@ -38,13 +76,54 @@ pub fn load_objs(filename: &str) -> Result<Vec<Obj>, tobj::LoadError> {
                 .collect();
 
             let indices: Vec<usize> = mesh.indices.iter().map(|idx| *idx as usize).collect();
-            get_vertex_array(indices, vertices, normals, texcoords)
+            let mut vertex_array = get_vertex_array(indices, vertices, normals, texcoords);
+            generate_tangents(&mut vertex_array);
+
+            vertex_array
         })
         .collect();
 
     Ok(objs)
 }
 
+/// Generates a flat ring on the XZ plane, tilted around the X axis.
+/// Both faces are generated, the top one facing +Y before the tilt.
+///
+/// The `u` texture coordinate goes from 0 on the inner edge to 1 on the outer edge,
+/// and `v` goes from 0 to 1 around the ring.
+pub fn create_ring(inner_radius: f32, outer_radius: f32, tilt: f32, segments: usize) -> Obj {
+    let rotation = quat_angle_axis(tilt, &Vec3::x());
+    let segments = segments.max(3);
+    let up = quat_rotate_vec3(&rotation, &Vec3::y());
+
+    let point = |segment: usize, radius: f32, u: f32| {
+        let v = segment as f32 / segments as f32;
+        let angle = v * 2.0 * PI;
+        let position = vec3(angle.cos() * radius, 0.0, angle.sin() * radius);
+        (quat_rotate_vec3(&rotation, &position), vec2(u, v))
+    };
+
+    let mut vertices = Vec::with_capacity(segments * 12);
+    for segment in 0..segments {
+        let quad = [
+            point(segment, inner_radius, 0.0),
+            point(segment, outer_radius, 1.0),
+            point(segment + 1, outer_radius, 1.0),
+            point(segment + 1, inner_radius, 0.0),
+        ];
+
+        for (normal, order) in [(up, [0, 2, 1, 0, 3, 2]), (-up, [0, 1, 2, 0, 2, 3])] {
+            vertices.extend(order.iter().map(|&i| {
+                let (position, tex_coords) = quad[i];
+                Vertex::new(position, normal, tex_coords)
+            }));
+        }
+    }
+
+    generate_tangents(&mut vertices);
+    vertices
+}
+
 fn get_vertex_array(
     indices: Vec<usize>,
     vertices: Vec<Vec3>,
@@ -59,3 +138,72 @@ fn get_vertex_array(
     }))
     .collect()
 }
+
+/// Computes the tangent of each vertex from the texture coordinates of its triangle.
+/// The tangent follows the direction in which `u` grows and is made perpendicular to the normal.
+fn generate_tangents(vertices: &mut [Vertex]) {
+    for triangle in vertices.chunks_mut(3) {
+        let [a, b, c] = triangle else {
+            continue;
+        };
+
+        let edge1 = b.model_position - a.model_position;
+        let edge2 = c.model_position - a.model_position;
+        let delta_uv1 = b.tex_coords - a.tex_coords;
+        let delta_uv2 = c.tex_coords - a.tex_coords;
+
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < f32::EPSILON {
+            // No texture coordinates to follow...
+            continue;
+        }
+
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / determinant;
+        for vertex in [a, b, c] {
+            // Gram-Schmidt, so the tangent ends up perpendicular to the normal.
+            let normal = vertex.normal;
+            vertex.tangent = (tangent - normal * normal.dot(&tangent))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(Vec3::zeros());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equal;
+
+    #[test]
+    fn test_sphere_tangents_are_perpendicular_to_normals() {
+        let objs = load_objs("assets/models/sphere.obj").unwrap();
+
+        for vertex in objs.iter().flatten() {
+            if vertex.tangent == Vec3::zeros() {
+                continue;
+            }
+
+            assert!(equal(vertex.tangent.magnitude(), 1.0, 1e-4));
+            assert!(equal(
+                vertex.tangent.dot(&vertex.normal.normalize()),
+                0.0,
+                1e-3
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ring_lies_between_its_radii() {
+        let ring = create_ring(1.5, 2.5, PI / 6.0, 32);
+        assert_eq!(ring.len(), 32 * 12);
+
+        for vertex in &ring {
+            let radius = vertex.model_position.magnitude();
+            assert!((1.5 - 1e-4..=2.5 + 1e-4).contains(&radius));
+            // The ring is flat, so every point is perpendicular to the normal.
+            assert!(equal(vertex.model_position.dot(&vertex.normal), 0.0, 1e-4));
+            // The ring is one unit wide.
+            assert!(equal(vertex.tex_coords.x, radius - 1.5, 1e-4));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use nalgebra_glm::{dot, vec4, Mat4, Vec2, Vec3};
+
+use crate::{obj::Obj, vertex::shader::Uniforms, Entity};
+
+/// A half-line in world space.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized direction of the ray.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Gets the point at distance `t` from the origin.
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// The smallest sphere (more or less) that contains a mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Computes the bounding sphere of the given meshes in model space.
+    pub fn from_objs(objs: &[Obj]) -> Self {
+        let mut min = Vec3::repeat(f32::INFINITY);
+        let mut max = Vec3::repeat(f32::NEG_INFINITY);
+        for vertex in objs.iter().flatten() {
+            min = min.inf(&vertex.model_position);
+            max = max.sup(&vertex.model_position);
+        }
+
+        if min.x > max.x {
+            return BoundingSphere {
+                center: Vec3::zeros(),
+                radius: 0.0,
+            };
+        }
+
+        let center = (min + max) / 2.0;
+        let radius = objs
+            .iter()
+            .flatten()
+            .map(|v| (v.model_position - center).magnitude())
+            .fold(0.0, f32::max);
+
+        BoundingSphere { center, radius }
+    }
+
+    /// Moves the sphere using the given model matrix.
+    /// If the matrix has a non uniform scale the biggest one is used.
+    pub fn transformed(&self, model_matrix: &Mat4) -> Self {
+        let center = model_matrix * self.center.push(1.0);
+        let scale = (0..3)
+            .map(|i| model_matrix.fixed_view::<3, 1>(0, i).magnitude())
+            .fold(0.0, f32::max);
+
+        BoundingSphere {
+            center: center.xyz(),
+            radius: self.radius * scale,
+        }
+    }
+
+    /// Computes the distance from the ray origin to the first hit with the sphere.
+    /// If the origin is inside the sphere the exit point is used.
+    pub fn intersect(&self, ray: &Ray) -> Option<f32> {
+        let oc = ray.origin - self.center;
+        let b = dot(&oc, &ray.direction);
+        let c = dot(&oc, &oc) - self.radius * self.radius;
+        let discriminant = b * b - c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrt = discriminant.sqrt();
+        [-b - sqrt, -b + sqrt].into_iter().find(|t| *t >= 0.0)
+    }
+}
+
+impl Entity {
+    /// Computes the bounding sphere of the entity in world space.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_objs(&self.objs).transformed(&self.model_matrix)
+    }
+}
+
+/// What the user clicked on.
+#[derive(Debug, Clone, Copy)]
+pub struct PickResult {
+    /// Index of the entity inside `Model::entities`.
+    pub entity: usize,
+    /// Point in world space where the ray hit the entity bounds.
+    pub point: Vec3,
+    /// Distance from the camera to the hit point.
+    pub distance: f32,
+}
+
+/// Converts a point of the framebuffer into a ray that goes from the camera into the world.
+///
+/// * `screen_position`: Position inside the framebuffer, the origin is on the top left corner.
+/// * `uniforms`: The uniforms used to render the frame.
+pub fn unproject(screen_position: Vec2, uniforms: &Uniforms) -> Ray {
+    let Uniforms {
+        view_matrix,
+        projection_matrix,
+        viewport_matrix,
+        ..
+    } = uniforms;
+
+    let inverse = (viewport_matrix * projection_matrix * view_matrix)
+        .try_inverse()
+        .unwrap_or(Mat4::identity());
+
+    // The viewport matrix keeps the NDC depth, so near is -1 and far is 1.
+    let unproject_depth = |depth: f32| {
+        let point = inverse * vec4(screen_position.x, screen_position.y, depth, 1.0);
+        point.xyz() / point.w
+    };
+
+    let near = unproject_depth(-1.0);
+    let far = unproject_depth(1.0);
+
+    Ray {
+        origin: near,
+        direction: (far - near).normalize(),
+    }
+}
+
+/// Finds the closest entity under the given framebuffer position.
+pub fn pick(screen_position: Vec2, uniforms: &Uniforms, entities: &[Entity]) -> Option<PickResult> {
+    let ray = unproject(screen_position, uniforms);
+
+    entities
+        .iter()
+        .enumerate()
+        .filter_map(|(entity, e)| {
+            e.bounding_sphere()
+                .intersect(&ray)
+                .map(|distance| PickResult {
+                    entity,
+                    point: ray.at(distance),
+                    distance,
+                })
+        })
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::{vec2, vec3};
+
+    use super::*;
+    use crate::{
+        camera::DEFAULT_FOV,
+        equal,
+        vertex::shader::{create_projection_matrix, create_view_matrix, create_viewport_matrix},
+    };
+
+    #[test]
+    fn test_center_of_screen_hits_sphere_in_front() {
+        let eye = vec3(0.0, 0.0, 20.0);
+        let uniforms = Uniforms {
+            camera_position: eye,
+            model_matrix: Mat4::identity(),
+            view_matrix: create_view_matrix(eye, Vec3::zeros(), vec3(0.0, 1.0, 0.0)),
+            projection_matrix: create_projection_matrix(800.0, 600.0, DEFAULT_FOV),
+            viewport_matrix: create_viewport_matrix(800.0, 600.0),
+            time: 0.0,
+        };
+
+        let ray = unproject(vec2(400.0, 300.0), &uniforms);
+        assert!(equal(ray.direction.z, -1.0, 1e-4));
+
+        let sphere = BoundingSphere {
+            center: Vec3::zeros(),
+            radius: 2.0,
+        };
+        let distance = sphere.intersect(&ray).unwrap();
+        assert!(equal(ray.at(distance).z, 2.0, 1e-2));
+
+        // The top left corner shouldn't see it...
+        let ray = unproject(vec2(0.0, 0.0), &uniforms);
+        assert!(sphere.intersect(&ray).is_none());
+    }
+}
@@ -1,111 +1,252 @@
 use fastnoise_lite::FastNoiseLite;
-use nalgebra_glm::{Mat4, Vec3};
+use nalgebra_glm::Vec3;
 use rayon::prelude::*;
 
 use crate::{
+    camera::Camera,
     color::Color,
-    fragment::{shaders::fragment_shader, triangle, Fragment},
+    fragment::{
+        lighting::LightingContext,
+        shaders::{fragment_shader, ShaderContext},
+        triangle, Fragment,
+    },
     framebuffer::Framebuffer,
     light::Light,
+    picking::BoundingSphere,
+    shadow::{ShadowMap, SHADOW_MAP_SIZE},
     vertex::{
         shader::{vertex_shader, Uniforms},
         Vertex,
     },
+    viewport::{Rect, ViewportCamera},
     Entity, Model,
 };
 
+/// How many pixels the light of emissive surfaces spreads.
+const BLOOM_RADIUS: usize = 6;
+/// How strong the light of emissive surfaces is when spread.
+const BLOOM_STRENGTH: f32 = 1.5;
+
 pub fn render(framebuffer: &mut Framebuffer, data: &Model, noise: &mut FastNoiseLite) {
     let Model {
+        view_type,
+        game_window,
+        viewport_layout,
         entities,
-        uniforms,
-        camera,
         ship,
-        textures,
         lights,
-        skybox,
-        view_type,
-        game_window,
+        ambient_light,
         ..
     } = data;
 
     match game_window {
         crate::GameWindow::Controls => {}
         crate::GameWindow::Simulation => {
-            skybox.render(framebuffer, uniforms, &camera.eye);
+            let framebuffer_size = (framebuffer.width, framebuffer.height);
 
-            let mut render_entities = Vec::with_capacity(1 + entities.len());
-            if matches!(view_type, crate::ViewType::FirstPerson) {
-                render_entities.push(&ship.entity);
-            }
+            // Lights attached to the ship keep shining even if the ship isn't rendered.
+            let lights: Vec<Light> = lights
+                .iter()
+                .cloned()
+                .chain(ship.entity.world_lights())
+                .chain(entities.iter().flat_map(|e| e.world_lights()))
+                .collect();
 
-            for e in entities.iter() {
-                render_entities.push(e);
-            }
+            // The shadow maps don't depend on the camera, so all viewports share them.
+            let casters: Vec<&Entity> = std::iter::once(&ship.entity)
+                .chain(entities.iter())
+                .filter(|e| !e.transparent)
+                .collect();
+            let shadow_maps: Vec<Option<ShadowMap>> = lights
+                .iter()
+                .map(|light| ShadowMap::render(light, &casters, SHADOW_MAP_SIZE))
+                .collect();
 
-            for entity in render_entities {
-                let Entity {
-                    objs,
-                    shaders,
-                    model_matrix,
-                    optimizations,
-                    use_screen_position,
-                    custom_depth,
-                    wireframe_color: color_of_lines,
-                    ..
-                } = entity;
-
-                for vertex_array in objs {
-                    // Vertex Shader
-                    // println!("Applying shaders...");
-                    let new_vertices = apply_shaders(vertex_array, uniforms, model_matrix);
-                    // println!("Vertex shader applied!");
-                    // for vertex in new_vertices.iter().take(25) {
-                    //     println!("Transformed vertex: {:?}", vertex);
-                    // }
-
-                    // Primitive assembly
-                    // println!("Assembly...");
-                    let triangles = assembly(&new_vertices, optimizations.frustum_cutting);
-                    // println!("Assembly done!");
-
-                    // Rasterization
-                    // println!("Applying rasterization...");
-                    let camera_direction = &camera.direction();
-                    let fragments = rasterize(
-                        triangles,
-                        if optimizations.camera_direction {
-                            Some(camera_direction)
-                        } else {
-                            None
-                        },
-                        use_screen_position,
-                        lights,
-                        *custom_depth,
-                        color_of_lines,
-                    );
-                    // println!("Rasterization applied!");
-
-                    // println!("Applying fragment shaders...");
-                    let fragments = fragments
-                        .into_iter()
-                        .map(|f| fragment_shader(f, shaders, uniforms, noise, textures))
-                        .collect();
-                    // println!("Fragment shaders applied!");
-
-                    // Fragment Processing
-                    // println!("Painting fragments...");
-                    paint_fragments(fragments, framebuffer);
-                    // println!("Fragments painted!");
+            // Every opaque entity casts shadows, even the ship when it isn't rendered.
+            let bounding_spheres: Vec<Option<BoundingSphere>> = std::iter::once(&ship.entity)
+                .chain(entities.iter())
+                .map(|e| (!e.transparent).then(|| e.bounding_sphere()))
+                .collect();
+
+            let lighting = LightingContext {
+                ambient: *ambient_light,
+                lights: &lights,
+                shadow_maps: &shadow_maps,
+                occluders: &[],
+            };
+
+            for viewport in viewport_layout.viewports(framebuffer.width, framebuffer.height) {
+                let (camera, uniforms) = viewport.camera_and_uniforms(data, framebuffer_size);
+                let render_ship = match viewport.camera {
+                    ViewportCamera::Main => matches!(
+                        view_type,
+                        crate::ViewType::FirstPerson | crate::ViewType::FreeFly
+                    ),
+                    // The camera is inside the ship...
+                    ViewportCamera::RearView => false,
+                    ViewportCamera::BirdEye => true,
+                };
+
+                if viewport.is_inset {
+                    framebuffer.clear_rect(&viewport.rect);
+                }
+
+                framebuffer.set_scissor(Some(viewport.rect));
+                render_view(
+                    framebuffer,
+                    data,
+                    noise,
+                    &camera,
+                    &uniforms,
+                    &lighting,
+                    &bounding_spheres,
+                    render_ship,
+                );
+                framebuffer.set_scissor(None);
+
+                if viewport.is_inset {
+                    framebuffer.set_current_color(Color::white());
+                    framebuffer.paint_rect_outline(&viewport.rect);
                 }
             }
+
+            framebuffer.apply_bloom(BLOOM_RADIUS, BLOOM_STRENGTH);
+        }
+    }
+}
+
+/// Renders the scene as seen by the given camera.
+/// Only the area inside the framebuffer scissor gets painted.
+///
+/// * `bounding_spheres`: Of the ship followed by the entities, `None` for the ones that don't cast shadows.
+#[allow(clippy::too_many_arguments)]
+fn render_view(
+    framebuffer: &mut Framebuffer,
+    data: &Model,
+    noise: &mut FastNoiseLite,
+    camera: &Camera,
+    uniforms: &Uniforms,
+    lighting: &LightingContext,
+    bounding_spheres: &[Option<BoundingSphere>],
+    render_ship: bool,
+) {
+    let Model {
+        entities,
+        ship,
+        textures,
+        skybox,
+        shader_registry,
+        ..
+    } = data;
+    let scissor = framebuffer.scissor();
+
+    let all_entities: Vec<&Entity> = std::iter::once(&ship.entity)
+        .chain(entities.iter())
+        .collect();
+
+    skybox.render(framebuffer, uniforms, &camera.eye);
+
+    // Transparent entities go after the opaque ones, from the farthest to the closest,
+    // so they get blended over everything behind them.
+    let skipped_entities = if render_ship { 0 } else { 1 };
+    let distance = |index: usize| (all_entities[index].model.translation - camera.eye).magnitude();
+    let mut order: Vec<usize> = (skipped_entities..all_entities.len()).collect();
+    order.sort_by(
+        |&a, &b| match (all_entities[a].transparent, all_entities[b].transparent) {
+            (true, true) => distance(b).total_cmp(&distance(a)),
+            (a_transparent, b_transparent) => a_transparent.cmp(&b_transparent),
+        },
+    );
+
+    for index in order {
+        let entity = all_entities[index];
+        // An entity can't cast a shadow onto itself.
+        let occluders: Vec<BoundingSphere> = bounding_spheres
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .filter_map(|(_, sphere)| *sphere)
+            .collect();
+        let lighting = LightingContext {
+            occluders: &occluders,
+            ..*lighting
+        };
+
+        let Entity {
+            objs,
+            shaders,
+            model_matrix,
+            optimizations,
+            use_screen_position,
+            custom_depth,
+            wireframe_color: color_of_lines,
+            material,
+            transparent,
+            ..
+        } = entity;
+        let uniforms = &Uniforms {
+            model_matrix: *model_matrix,
+            ..*uniforms
+        };
+
+        for vertex_array in objs {
+            // Vertex Shader
+            // println!("Applying shaders...");
+            let new_vertices = apply_shaders(vertex_array, uniforms);
+            // println!("Vertex shader applied!");
+            // for vertex in new_vertices.iter().take(25) {
+            //     println!("Transformed vertex: {:?}", vertex);
+            // }
+
+            // Primitive assembly
+            // println!("Assembly...");
+            let triangles = assembly(&new_vertices, optimizations.frustum_cutting);
+            // println!("Assembly done!");
+
+            // Rasterization
+            // println!("Applying rasterization...");
+            let camera_direction = &camera.direction();
+            let fragments = rasterize(
+                triangles,
+                if optimizations.camera_direction {
+                    Some(camera_direction)
+                } else {
+                    None
+                },
+                use_screen_position,
+                lighting.lights,
+                *custom_depth,
+                color_of_lines,
+                scissor.as_ref(),
+            );
+            // println!("Rasterization applied!");
+
+            // println!("Applying fragment shaders...");
+            let mut context = ShaderContext {
+                uniforms,
+                textures,
+                lighting: &lighting,
+                material,
+                noise: &mut *noise,
+            };
+            let fragments = fragments
+                .into_iter()
+                .map(|f| fragment_shader(f, shaders, shader_registry, &mut context))
+                .collect();
+            // println!("Fragment shaders applied!");
+
+            // Fragment Processing
+            // println!("Painting fragments...");
+            paint_fragments(fragments, framebuffer, *transparent);
+            // println!("Fragments painted!");
         }
     }
 }
 
-fn apply_shaders(vertices: &[Vertex], uniforms: &Uniforms, model_matrix: &Mat4) -> Vec<Vertex> {
+fn apply_shaders(vertices: &[Vertex], uniforms: &Uniforms) -> Vec<Vertex> {
     vertices
         .par_iter()
-        .map(|v| vertex_shader(v, uniforms, model_matrix))
+        .map(|v| vertex_shader(v, uniforms))
         .collect()
 }
 
@@ -144,6 +285,7 @@ fn rasterize(
     lights: &[Light],
     custom_depth: Option<f32>,
     wirefragme_color: &Option<Color>,
+    scissor: Option<&Rect>,
 ) -> Vec<Fragment> {
     triangles
         .par_iter()
@@ -157,14 +299,21 @@ fn rasterize(
                 lights,
                 custom_depth,
                 wirefragme_color,
+                scissor,
             )
         })
         .collect()
 }
 
-fn paint_fragments(fragments: Vec<Fragment>, framebuffer: &mut Framebuffer) {
+fn paint_fragments(fragments: Vec<Fragment>, framebuffer: &mut Framebuffer, transparent: bool) {
     for fragment in fragments {
         framebuffer.set_current_color(fragment.color);
-        let _ = framebuffer.paint_point(fragment.position, fragment.depth);
+        framebuffer.set_current_glow(fragment.emission);
+        let _ = if transparent {
+            framebuffer.blend_point(fragment.position, fragment.depth, fragment.alpha)
+        } else {
+            framebuffer.paint_point(fragment.position, fragment.depth)
+        };
     }
+    framebuffer.set_current_glow(Color::black());
 }
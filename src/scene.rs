@@ -0,0 +1,376 @@
+pub mod noise;
+
+use std::fs;
+
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color::Color,
+    fragment::{ellipsis::Ellipsis, material::Material},
+    light::{AmbientLightIntensity, Light},
+    obj::MeshSource,
+    skybox::{Skybox, SkyboxSettings},
+    vertex::shader::create_model_matrix,
+    Entity, EntityModel, EntityOptimizations, EntityShader, Model, ParentLink,
+};
+
+/// The scene that gets rendered, ready to be put inside a `Model`.
+pub struct Scene {
+    pub entities: Vec<Entity>,
+    pub lights: Vec<Light>,
+    pub ambient_light: AmbientLightIntensity,
+    pub skybox: Skybox,
+}
+
+/// A scene as it's written on a scene file (RON).
+/// Every entity must have a `MeshSource`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub ambient_light: AmbientLightIntensity,
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    pub skybox: SkyboxSettings,
+    pub entities: Vec<EntityDescription>,
+}
+
+/// An `Entity` as it's written on a scene file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntityDescription {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub mesh: MeshSource,
+    pub model: EntityModel,
+    /// The orbit of the entity, if any.
+    #[serde(default)]
+    pub orbit: Option<Ellipsis>,
+    /// The shader layers, applied from first to last.
+    pub shaders: Vec<EntityShader>,
+    #[serde(default = "Material::default")]
+    pub material: Material,
+    /// Lights that move along with the entity, defined in model space.
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    pub optimizations: EntityOptimizations,
+    #[serde(default)]
+    pub use_screen_position: bool,
+    #[serde(default)]
+    pub custom_depth: Option<f32>,
+    #[serde(default)]
+    pub wireframe_color: Option<Color>,
+    /// The entity this one follows around, by its index on `entities`.
+    #[serde(default)]
+    pub parent: Option<ParentLink>,
+    #[serde(default)]
+    pub transparent: bool,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Write(ron::Error),
+    /// The mesh of an entity couldn't be loaded.
+    Mesh(MeshSource, tobj::LoadError),
+    /// The parent of an entity is out of bounds or the entity itself.
+    InvalidParent {
+        entity: usize,
+        parent: usize,
+    },
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "Couldn't access the scene file: {err}"),
+            SceneError::Parse(err) => write!(f, "Couldn't parse the scene file: {err}"),
+            SceneError::Write(err) => write!(f, "Couldn't write the scene file: {err}"),
+            SceneError::Mesh(mesh, err) => write!(f, "Couldn't load the mesh {mesh:?}: {err}"),
+            SceneError::InvalidParent { entity, parent } => {
+                write!(f, "The entity {entity} can't follow the entity {parent}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl SceneDescription {
+    /// Reads a scene file.
+    pub fn load(file_path: &str) -> Result<Self, SceneError> {
+        let contents = fs::read_to_string(file_path).map_err(SceneError::Io)?;
+        ron::from_str(&contents).map_err(SceneError::Parse)
+    }
+
+    /// Writes the scene into a file, overwriting it if it already exists.
+    pub fn save(&self, file_path: &str) -> Result<(), SceneError> {
+        let contents =
+            ron::ser::to_string_pretty(self, PrettyConfig::default()).map_err(SceneError::Write)?;
+        fs::write(file_path, contents).map_err(SceneError::Io)
+    }
+
+    /// Describes the scene currently inside the model.
+    /// Entities without a mesh file are skipped, the ship isn't part of the scene.
+    pub fn from_model(model: &Model) -> Self {
+        let Model {
+            entities,
+            lights,
+            ambient_light,
+            skybox,
+            ..
+        } = model;
+
+        SceneDescription {
+            ambient_light: *ambient_light,
+            lights: lights.clone(),
+            skybox: skybox.settings,
+            entities: entities
+                .iter()
+                .filter_map(EntityDescription::from_entity)
+                .collect(),
+        }
+    }
+
+    /// Loads the meshes of the entities and generates the skybox.
+    pub fn build(self) -> Result<Scene, SceneError> {
+        let SceneDescription {
+            ambient_light,
+            lights,
+            skybox,
+            entities,
+        } = self;
+
+        let entity_count = entities.len();
+        for (entity, description) in entities.iter().enumerate() {
+            if let Some(ParentLink { index: parent, .. }) = description.parent {
+                if parent >= entity_count || parent == entity {
+                    return Err(SceneError::InvalidParent { entity, parent });
+                }
+            }
+        }
+
+        let entities = entities
+            .into_iter()
+            .map(EntityDescription::build)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Scene {
+            entities,
+            lights,
+            ambient_light,
+            skybox: Skybox::new(skybox.star_count, skybox.star_radius),
+        })
+    }
+}
+
+impl EntityDescription {
+    /// Describes the entity, returns `None` if it doesn't have a `MeshSource`.
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        let mesh = entity.mesh.clone()?;
+
+        Some(EntityDescription {
+            name: entity.name.clone(),
+            mesh,
+            model: entity.model.clone(),
+            orbit: entity.ellipsis.clone(),
+            shaders: entity.shaders.clone(),
+            material: entity.material.clone(),
+            lights: entity.lights.clone(),
+            optimizations: entity.optimizations,
+            use_screen_position: entity.use_screen_position,
+            custom_depth: entity.custom_depth,
+            wireframe_color: entity.wireframe_color,
+            parent: entity.parent,
+            transparent: entity.transparent,
+        })
+    }
+
+    /// Loads or generates the mesh of the entity.
+    pub fn build(self) -> Result<Entity, SceneError> {
+        let EntityDescription {
+            name,
+            mesh,
+            model,
+            orbit,
+            shaders,
+            material,
+            lights,
+            optimizations,
+            use_screen_position,
+            custom_depth,
+            wireframe_color,
+            parent,
+            transparent,
+        } = self;
+
+        let objs = mesh
+            .load()
+            .map_err(|err| SceneError::Mesh(mesh.clone(), err))?;
+
+        Ok(Entity {
+            name,
+            objs,
+            mesh: Some(mesh),
+            shaders,
+            model_matrix: create_model_matrix(model.translation, model.scale, model.orientation),
+            optimizations,
+            use_screen_position,
+            model,
+            custom_depth,
+            ellipsis: orbit,
+            wireframe_color,
+            material,
+            lights,
+            parent,
+            transparent,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fragment::{
+            lighting::LightingContext,
+            planets::{create_face_planet, create_sun},
+            shaders::{fragment_shader, ShaderContext, ShaderRegistry},
+            Fragment,
+        },
+        texture::GameTextures,
+        vertex::shader::{create_noise, Uniforms},
+    };
+    use nalgebra_glm::{vec2, vec3, Mat4};
+
+    #[test]
+    fn test_scene_round_trip() {
+        let description = SceneDescription {
+            ambient_light: 0.1,
+            lights: vec![],
+            skybox: SkyboxSettings {
+                star_count: 10,
+                star_radius: 50.0,
+            },
+            entities: [create_sun(vec3(0.0, 1.0, 0.0)), create_face_planet()]
+                .iter()
+                .filter_map(EntityDescription::from_entity)
+                .collect(),
+        };
+
+        let text = ron::ser::to_string_pretty(&description, PrettyConfig::default()).unwrap();
+        let loaded: SceneDescription = ron::from_str(&text).unwrap();
+
+        // Saving the loaded scene again gives back the same file.
+        let reloaded = ron::ser::to_string_pretty(&loaded, PrettyConfig::default()).unwrap();
+        assert_eq!(text, reloaded);
+
+        assert_eq!(loaded.entities.len(), 2);
+        assert_eq!(loaded.entities[0].model.translation, vec3(0.0, 1.0, 0.0));
+        assert!(loaded.entities[1].orbit.is_some());
+    }
+
+    #[test]
+    fn test_layers_without_colors_use_white() {
+        let shaders: Vec<EntityShader> = ron::from_str(
+            "[
+                (BaseColor, [], Replace),
+                (Stripe(stripe_width: 0.1), [], Replace),
+                (MovingStripes(speed: 0.001, stripe_width: 0.1), [], Replace),
+                (FBmShader(
+                    zoom: 200.0,
+                    speed: 0.1,
+                    fractal: (
+                        octaves: 4,
+                        lacunarity: 2.0,
+                        gain: 0.5,
+                        weighted_strength: 0.0,
+                        f_type: None,
+                    ),
+                ), [], Replace),
+                (Atmosphere(density: 1.0, falloff: 2.0), [], Replace),
+            ]",
+        )
+        .unwrap();
+
+        let uniforms = Uniforms {
+            camera_position: vec3(0.0, 0.0, 5.0),
+            model_matrix: Mat4::identity(),
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 0.0,
+        };
+        let lighting = LightingContext {
+            ambient: 1.0,
+            lights: &[],
+            shadow_maps: &[],
+            occluders: &[],
+        };
+        let textures = GameTextures::new("assets/textures/");
+        let mut noise = create_noise();
+        let mut context = ShaderContext {
+            uniforms: &uniforms,
+            textures: &textures,
+            lighting: &lighting,
+            material: &Material::default(),
+            noise: &mut noise,
+        };
+        let registry = ShaderRegistry::with_builtins();
+
+        let mut shade = |shaders: &[EntityShader]| {
+            let fragment = Fragment {
+                normal: vec3(0.0, 0.0, 1.0),
+                ..Fragment::new(vec2(0.0, 0.0), Color::black(), 0.0, vec3(0.3, 0.6, 0.0))
+            };
+            fragment_shader(fragment, shaders, &registry, &mut context).color
+        };
+
+        assert_eq!(shade(&shaders[..1]), Color::white());
+
+        // The rest of the layers don't have a color to index either.
+        for shader in shaders.iter().skip(1) {
+            shade(std::slice::from_ref(shader));
+        }
+    }
+
+    #[test]
+    fn test_invalid_parents_are_rejected() {
+        let scene_with_parent = |parent| {
+            let mut planet = EntityDescription::from_entity(&create_face_planet()).unwrap();
+            planet.parent = Some(ParentLink {
+                index: parent,
+                scale: 1.0,
+                spin: 0.0,
+            });
+            SceneDescription {
+                ambient_light: 0.1,
+                lights: vec![],
+                skybox: SkyboxSettings {
+                    star_count: 10,
+                    star_radius: 50.0,
+                },
+                entities: vec![
+                    EntityDescription::from_entity(&create_sun(vec3(0.0, 0.0, 0.0))).unwrap(),
+                    planet,
+                ],
+            }
+        };
+
+        assert!(matches!(
+            scene_with_parent(2).build(),
+            Err(SceneError::InvalidParent {
+                entity: 1,
+                parent: 2
+            })
+        ));
+        assert!(matches!(
+            scene_with_parent(1).build(),
+            Err(SceneError::InvalidParent {
+                entity: 1,
+                parent: 1
+            })
+        ));
+        assert!(scene_with_parent(0).build().is_ok());
+    }
+}
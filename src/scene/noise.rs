@@ -0,0 +1,57 @@
+//! Mirrors of the fastnoise-lite enums, so the noise configs can be saved in a scene file.
+
+use fastnoise_lite::{
+    CellularDistanceFunction, CellularReturnType, DomainWarpType, FractalType, NoiseType,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "NoiseType")]
+pub enum NoiseTypeDef {
+    OpenSimplex2,
+    OpenSimplex2S,
+    Cellular,
+    Perlin,
+    ValueCubic,
+    Value,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "FractalType")]
+pub enum FractalTypeDef {
+    None,
+    FBm,
+    Ridged,
+    PingPong,
+    DomainWarpProgressive,
+    DomainWarpIndependent,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CellularDistanceFunction")]
+pub enum CellularDistanceFunctionDef {
+    Euclidean,
+    EuclideanSq,
+    Manhattan,
+    Hybrid,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "CellularReturnType")]
+pub enum CellularReturnTypeDef {
+    CellValue,
+    Distance,
+    Distance2,
+    Distance2Add,
+    Distance2Sub,
+    Distance2Mul,
+    Distance2Div,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "DomainWarpType")]
+pub enum DomainWarpTypeDef {
+    OpenSimplex2,
+    OpenSimplex2Reduced,
+    BasicGrid,
+}
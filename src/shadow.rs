@@ -0,0 +1,324 @@
+use std::f32::consts::PI;
+
+use nalgebra_glm::{Mat4, Vec3};
+use rayon::prelude::*;
+
+use crate::{
+    fragment::{triangle, Fragment},
+    light::{Light, LightKind},
+    vertex::{
+        shader::{
+            create_projection_matrix, create_view_matrix, create_viewport_matrix, vertex_shader,
+            Uniforms,
+        },
+        Vertex,
+    },
+    viewport::Rect,
+    Entity,
+};
+
+/// Width and height in pixels of each face of a shadow map.
+pub const SHADOW_MAP_SIZE: usize = 256;
+/// Distance a fragment can be behind the stored depth and still be lit, avoids shadow acne.
+const SHADOW_BIAS: f32 = 0.15;
+/// Directions (and up vectors) each face of a cube map looks at.
+const CUBE_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+    (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+    (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+    (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 1.0, 0.0)),
+    (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0)),
+];
+
+/// The scene as seen from a light, storing only how far the closest surface is.
+struct ShadowFace {
+    uniforms: Uniforms,
+    /// Distance from the light to the closest surface on each pixel.
+    depth: Vec<f32>,
+}
+
+/// The distances from a light to the surfaces it illuminates.
+/// Point lights use a cube map (six faces), spot lights use a single face.
+pub struct ShadowMap {
+    light_position: Vec3,
+    faces: Vec<ShadowFace>,
+    size: usize,
+}
+
+impl ShadowMap {
+    /// Renders the shadow map of a light.
+    /// Returns `None` if the light doesn't cast shadows or is a `LightKind::Directional`.
+    ///
+    /// * `light`: The light in world space.
+    /// * `casters`: The entities that cast shadows.
+    /// * `size`: Width and height in pixels of each face.
+    pub fn render(light: &Light, casters: &[&Entity], size: usize) -> Option<Self> {
+        if !light.casts_shadows {
+            return None;
+        }
+
+        let faces: Vec<(Vec3, Vec3, f32)> = match light.kind {
+            LightKind::Directional { .. } => return None,
+            LightKind::Point { .. } => CUBE_FACES
+                .iter()
+                .map(|(direction, up)| (*direction, *up, PI / 2.0))
+                .collect(),
+            LightKind::Spot {
+                direction,
+                outer_angle,
+                ..
+            } => {
+                let direction = direction.normalize();
+                let up = if direction.y.abs() > 0.99 {
+                    Vec3::z()
+                } else {
+                    Vec3::y()
+                };
+                vec![(direction, up, (2.0 * outer_angle).min(PI * 0.9))]
+            }
+        };
+
+        // Entities that surround the light, like the sun, would block all of it.
+        let casters: Vec<&Entity> = casters
+            .iter()
+            .filter(|e| {
+                let sphere = e.bounding_sphere();
+                (sphere.center - light.position).magnitude() >= sphere.radius
+            })
+            .copied()
+            .collect();
+
+        let faces = faces
+            .into_iter()
+            .map(|(direction, up, fov)| {
+                let uniforms = Uniforms {
+                    camera_position: light.position,
+                    model_matrix: Mat4::identity(),
+                    view_matrix: create_view_matrix(light.position, light.position + direction, up),
+                    projection_matrix: create_projection_matrix(size as f32, size as f32, fov),
+                    viewport_matrix: create_viewport_matrix(size as f32, size as f32),
+                    time: 0.0,
+                };
+                let depth = render_depth(&uniforms, &light.position, &casters, size);
+
+                ShadowFace { uniforms, depth }
+            })
+            .collect();
+
+        Some(ShadowMap {
+            light_position: light.position,
+            faces,
+            size,
+        })
+    }
+
+    /// Computes how much of the light reaches the given point, in world space.
+    /// Uses percentage closer filtering, so the edges of the shadows are a bit soft.
+    pub fn visibility(&self, position: &Vec3) -> f32 {
+        let to_point = position - self.light_position;
+        let distance = to_point.magnitude();
+        let face = if self.faces.len() == CUBE_FACES.len() {
+            cube_face(&to_point)
+        } else {
+            0
+        };
+        let ShadowFace { uniforms, depth } = &self.faces[face];
+
+        let clip = uniforms.projection_matrix * uniforms.view_matrix * position.push(1.0);
+        if clip.w <= 0.0 {
+            return 1.0;
+        }
+        let screen = uniforms.viewport_matrix * (clip / clip.w);
+        let (x, y) = (screen.x.floor() as isize, screen.y.floor() as isize);
+
+        let mut lit = 0;
+        let mut samples = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (sx, sy) = (x + dx, y + dy);
+                if sx < 0 || sy < 0 || sx >= self.size as isize || sy >= self.size as isize {
+                    continue;
+                }
+
+                samples += 1;
+                if distance - SHADOW_BIAS <= depth[sy as usize * self.size + sx as usize] {
+                    lit += 1;
+                }
+            }
+        }
+
+        if samples == 0 {
+            1.0
+        } else {
+            lit as f32 / samples as f32
+        }
+    }
+}
+
+/// Finds the face of the cube map that the direction points to.
+fn cube_face(direction: &Vec3) -> usize {
+    let abs = direction.abs();
+    if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x >= 0.0 {
+            0
+        } else {
+            1
+        }
+    } else if abs.y >= abs.z {
+        if direction.y >= 0.0 {
+            2
+        } else {
+            3
+        }
+    } else if direction.z >= 0.0 {
+        4
+    } else {
+        5
+    }
+}
+
+/// Rasterizes the casters without shading them, keeping the closest distance to the light.
+fn render_depth(
+    uniforms: &Uniforms,
+    light_position: &Vec3,
+    casters: &[&Entity],
+    size: usize,
+) -> Vec<f32> {
+    let mut depth = vec![f32::INFINITY; size * size];
+    let area = Rect::new(0, 0, size, size);
+
+    for entity in casters {
+        let uniforms = &Uniforms {
+            model_matrix: entity.model_matrix,
+            ..*uniforms
+        };
+        for vertex_array in &entity.objs {
+            let vertices: Vec<Vertex> = vertex_array
+                .par_iter()
+                .map(|v| vertex_shader(v, uniforms))
+                .collect();
+
+            let fragments: Vec<Fragment> = vertices
+                .par_chunks(3)
+                .filter(|tri| {
+                    // Triangles that go behind the light get projected all over the place.
+                    tri.iter()
+                        .all(|v| (-1.0..1.0).contains(&v.frustum_position.z))
+                })
+                .flat_map(|tri| {
+                    triangle(
+                        &tri[0],
+                        &tri[1],
+                        &tri[2],
+                        None,
+                        &false,
+                        &[],
+                        None,
+                        &None,
+                        Some(&area),
+                    )
+                })
+                .collect();
+
+            for fragment in fragments {
+                let (x, y) = (fragment.position.x as usize, fragment.position.y as usize);
+                if x >= size || y >= size {
+                    continue;
+                }
+
+                let distance = (fragment.world_position - light_position).magnitude();
+                let idx = y * size + x;
+                depth[idx] = depth[idx].min(distance);
+            }
+        }
+    }
+
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::vec3;
+
+    use super::*;
+    use crate::{
+        color::Color, equal, fragment::planets::create_default_planet_model, light::Attenuation,
+        obj::Obj, EntityOptimizations,
+    };
+
+    /// A square facing the light on the XY plane at the given depth.
+    fn square(z: f32) -> Entity {
+        let corners = [
+            vec3(-2.0, -2.0, z),
+            vec3(2.0, -2.0, z),
+            vec3(2.0, 2.0, z),
+            vec3(-2.0, 2.0, z),
+        ];
+        let normal = vec3(0.0, 0.0, 1.0);
+        let vertex = |i: usize| Vertex::new(corners[i], normal, nalgebra_glm::Vec2::zeros());
+        let objs: Vec<Obj> = vec![vec![
+            vertex(0),
+            vertex(1),
+            vertex(2),
+            vertex(0),
+            vertex(2),
+            vertex(3),
+        ]];
+        Entity {
+            name: None,
+            objs,
+            mesh: None,
+            shaders: vec![],
+            model_matrix: nalgebra_glm::Mat4::identity(),
+            optimizations: EntityOptimizations {
+                camera_direction: false,
+                frustum_cutting: false,
+            },
+            use_screen_position: false,
+            model: create_default_planet_model(),
+            custom_depth: None,
+            ellipsis: None,
+            wireframe_color: None,
+            material: crate::fragment::material::Material::default(),
+            lights: vec![],
+            parent: None,
+            transparent: false,
+        }
+    }
+
+    #[test]
+    fn test_point_light_shadow_map() {
+        let light = Light {
+            kind: LightKind::Point {
+                attenuation: Attenuation::NONE,
+            },
+            position: Vec3::zeros(),
+            color: Color::white(),
+            intensity: 1.0,
+            radius: 0.0,
+            casts_shadows: true,
+        };
+        let wall = square(-5.0);
+        let shadow_map = ShadowMap::render(&light, &[&wall], 64).unwrap();
+
+        // Something behind the wall is shadowed...
+        assert!(equal(
+            shadow_map.visibility(&vec3(0.0, 0.0, -10.0)),
+            0.0,
+            1e-5
+        ));
+        // ...the wall itself is lit...
+        assert!(equal(
+            shadow_map.visibility(&vec3(0.0, 0.0, -5.0)),
+            1.0,
+            1e-5
+        ));
+        // ...and so is everything on the other side of the light.
+        assert!(equal(
+            shadow_map.visibility(&vec3(0.0, 0.0, 10.0)),
+            1.0,
+            1e-5
+        ));
+    }
+}
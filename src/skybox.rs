@@ -1,6 +1,7 @@
 use core::f32;
 use nalgebra_glm::{vec2, Vec3, Vec4};
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 
 use crate::{framebuffer::Framebuffer, vertex::shader::Uniforms};
@@ -11,8 +12,17 @@ pub struct Star {
     size: u8,
 }
 
+/// The parameters used to generate the stars of a `Skybox`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SkyboxSettings {
+    pub star_count: usize,
+    /// Distance from the camera to the stars.
+    pub star_radius: f32,
+}
+
 pub struct Skybox {
     stars: Vec<Star>,
+    pub settings: SkyboxSettings,
 }
 
 impl Skybox {
@@ -41,7 +51,13 @@ impl Skybox {
             });
         }
 
-        Skybox { stars }
+        Skybox {
+            stars,
+            settings: SkyboxSettings {
+                star_count,
+                star_radius,
+            },
+        }
     }
 
     pub fn render(
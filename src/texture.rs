@@ -1,41 +1,85 @@
-use std::{fs::File, io::BufReader};
+pub mod sampler;
+
+use std::{collections::HashMap, fs::File, io::BufReader};
 
 use image::{
-    codecs::gif::GifDecoder, AnimationDecoder, Frame, GenericImageView, ImageDecoder, ImageReader,
-    Pixel,
+    codecs::gif::GifDecoder,
+    error::{DecodingError, ImageFormatHint},
+    AnimationDecoder, Frame, GenericImageView, ImageDecoder, ImageError, ImageFormat, ImageReader,
+    ImageResult, Pixel, Rgb, RgbImage,
 };
+use nalgebra_glm::{vec2, Vec2};
+use serde::{Deserialize, Serialize};
 
 use crate::color::Color;
 
-#[derive(Debug, Clone, Copy)]
+use self::sampler::{Filter, Sampler, WrapMode};
+
+/// How long a frame lasts if the GIF doesn't say, in ms.
+const DEFAULT_FRAME_DELAY: f32 = 100.0;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Textures {
     Space,
     Instructions,
+    /// A tangent space normal map of craters, for `ShaderType::NormalMap`.
+    CratersNormal,
+    /// A texture added with `GameTextures::add_baked`, by its index.
+    /// Only exists while the game runs, so entities should be baked after loading a scene.
+    Baked(usize),
 }
 
 pub struct GameTextures {
     pub space: Texture,
     pub instructions: Texture,
+    pub craters_normal: Texture,
+    /// Shader layers baked into textures.
+    pub baked: Vec<Texture>,
+    /// GIFs used by `ShaderType::AnimatedTexture`, by their path.
+    pub animated: HashMap<String, AnimatedTexture>,
 }
 
 impl GameTextures {
     pub fn new(asset_dir: &str) -> Self {
         let space = format!("{}{}", asset_dir, "space.png");
         let instructions = format!("{}{}", asset_dir, "instructions.jpg");
+        let craters_normal = format!("{}{}", asset_dir, "craters_normal.png");
 
         let space = Texture::new(&space);
         let instructions = Texture::new(&instructions);
+        let craters_normal = Texture::new(&craters_normal);
 
         GameTextures {
             space,
             instructions,
+            craters_normal,
+            baked: vec![],
+            animated: HashMap::new(),
+        }
+    }
+
+    /// Loads a GIF so `ShaderType::AnimatedTexture` can use it, if it isn't loaded already.
+    pub fn load_animated(&mut self, file_path: &str) -> ImageResult<()> {
+        if !self.animated.contains_key(file_path) {
+            self.animated
+                .insert(file_path.to_string(), AnimatedTexture::new(file_path)?);
         }
+        Ok(())
     }
 
-    pub fn get_texture(&self, id: Textures) -> &Texture {
+    /// Stores a baked texture, returning the id to use it.
+    pub fn add_baked(&mut self, texture: Texture) -> Textures {
+        self.baked.push(texture);
+        Textures::Baked(self.baked.len() - 1)
+    }
+
+    /// Returns `None` for baked textures that don't exist, like the ones written on a scene file.
+    pub fn get_texture(&self, id: Textures) -> Option<&Texture> {
         match id {
-            Textures::Space => &self.space,
-            Textures::Instructions => &self.instructions,
+            Textures::Space => Some(&self.space),
+            Textures::Instructions => Some(&self.instructions),
+            Textures::CratersNormal => Some(&self.craters_normal),
+            Textures::Baked(idx) => self.baked.get(idx),
         }
     }
 }
@@ -43,42 +87,119 @@ impl GameTextures {
 pub struct Texture {
     pub width: u32,
     pub height: u32,
+    /// The texture at full size first, then halving its size until it's 1x1.
+    levels: Vec<MipLevel>,
+}
+
+/// One of the sizes of a texture.
+struct MipLevel {
+    width: u32,
+    height: u32,
     colors: Vec<Color>,
 }
 
 pub struct AnimatedTexture {
     pub width: u32,
     pub height: u32,
-    frames: Vec<Frame>,
+    /// Every frame as a texture, so they can be sampled like one.
+    frames: Vec<Texture>,
+    /// How long each frame lasts in ms, as the GIF says.
+    delays: Vec<f32>,
     pub frame_count: usize,
 }
 
 impl AnimatedTexture {
-    pub fn new(file_path: &str) -> Self {
-        let file_in = BufReader::new(File::open(file_path).unwrap());
-        let decoder = GifDecoder::new(file_in).unwrap();
+    /// Decodes every frame of the GIF. GIFs without frames are an error, there'd be nothing to show.
+    pub fn new(file_path: &str) -> ImageResult<Self> {
+        let file_in = BufReader::new(File::open(file_path)?);
+        let decoder = GifDecoder::new(file_in)?;
         let (width, height) = decoder.dimensions();
-        let frames = decoder.into_frames();
-        let frames = frames.collect_frames().expect("error decoding gif");
+        let frames = decoder.into_frames().collect_frames()?;
+        if frames.is_empty() {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Exact(ImageFormat::Gif),
+                "the GIF has no frames",
+            )));
+        }
         let frame_count = frames.len();
 
-        Self {
+        let delays = frames
+            .iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                match numer as f32 / denom as f32 {
+                    // Lots of GIFs don't set a delay, browsers play them at 10 FPS.
+                    delay if delay > 0.0 => delay,
+                    _ => DEFAULT_FRAME_DELAY,
+                }
+            })
+            .collect();
+        let frames = frames.iter().map(frame_to_texture).collect();
+
+        Ok(Self {
             width,
             height,
             frames,
+            delays,
             frame_count,
-        }
+        })
     }
 
     /// Get's the color of the pixel positioned on the frame `t`.
     pub fn get_pixel_color(&self, t: usize, x: u32, y: u32) -> Color {
-        let pixel = self.frames[t].buffer().get_pixel(x, y).to_rgb();
-        let r = pixel[0];
-        let g = pixel[1];
-        let b = pixel[2];
+        self.frames[t].levels[0].texel(x, y)
+    }
 
-        Color { r, g, b }
+    /// The frame `t` as a texture.
+    pub fn frame(&self, t: usize) -> &Texture {
+        &self.frames[t]
     }
+
+    /// Which frame is shown after `time` ms of animation.
+    ///
+    /// * `fps`: How many frames are shown per second, if `None` the delays of the GIF are used.
+    /// * `looping`: If false, the last frame stays once the animation ends.
+    pub fn frame_at(&self, time: f32, fps: Option<f32>, looping: bool) -> usize {
+        let last = self.frame_count.saturating_sub(1);
+        let time = time.max(0.0);
+
+        if let Some(fps) = fps {
+            let frame = (time * fps / 1000.0) as usize;
+            return if looping {
+                frame % self.frame_count.max(1)
+            } else {
+                frame.min(last)
+            };
+        }
+
+        let duration: f32 = self.delays.iter().sum();
+        let mut time = if looping {
+            time.rem_euclid(duration)
+        } else {
+            time
+        };
+        for (frame, delay) in self.delays.iter().enumerate() {
+            if time < *delay {
+                return frame;
+            }
+            time -= delay;
+        }
+
+        last
+    }
+}
+
+fn frame_to_texture(frame: &Frame) -> Texture {
+    let buffer = frame.buffer();
+    let colors = buffer
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.to_rgb().0;
+            Color { r, g, b }
+        })
+        .collect();
+
+    Texture::from_colors(buffer.width(), buffer.height(), colors)
 }
 
 impl Texture {
@@ -104,20 +225,268 @@ impl Texture {
             }
         }
 
-        Texture {
+        Texture::from_colors(width, height, colors)
+    }
+
+    /// Creates a texture from its colors, ordered by rows, generating its mipmaps.
+    pub fn from_colors(width: u32, height: u32, colors: Vec<Color>) -> Self {
+        assert_eq!(colors.len(), (width * height) as usize);
+
+        let mut levels = vec![MipLevel {
             width,
             height,
             colors,
+        }];
+        while let Some(level) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+            levels.push(level.downsample());
+        }
+
+        Texture {
+            width,
+            height,
+            levels,
         }
     }
 
+    /// Writes the texture into an image, the format depends on the extension of the path.
+    pub fn save(&self, file_path: &str) -> ImageResult<()> {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let Color { r, g, b } = self.levels[0].texel(x, y);
+            Rgb([r, g, b])
+        })
+        .save(file_path)
+    }
+
+    /// Gets the closest texel to the texture coordinates on the full size texture.
+    /// Coordinates outside of 0 to 1 get clamped.
     pub fn get_pixel_color(&self, u: f32, v: f32) -> Color {
-        let x = (u * self.width as f32) as u32;
-        let y = (v * self.height as f32) as u32;
+        self.levels[0].nearest(vec2(u, v), WrapMode::Clamp)
+    }
+
+    /// Samples the texture with the given sampler.
+    ///
+    /// * `uv`: The texture coordinates.
+    /// * `lod`: Which mipmap level to use, 0 being the full size texture. See `Texture::lod`.
+    pub fn sample(&self, uv: Vec2, lod: f32, sampler: &Sampler) -> Color {
+        let lod = if sampler.mipmaps {
+            lod.clamp(0.0, (self.levels.len() - 1) as f32)
+        } else {
+            0.0
+        };
+
+        match sampler.filter {
+            Filter::Nearest => self.levels[lod.round() as usize].nearest(uv, sampler.wrap),
+            Filter::Bilinear => {
+                let level = lod.floor() as usize;
+                let color = self.levels[level].bilinear(uv, sampler.wrap);
+                match self.levels.get(level + 1) {
+                    Some(next) if lod.fract() > 0.0 => {
+                        color.lerp(&next.bilinear(uv, sampler.wrap), lod.fract())
+                    }
+                    _ => color,
+                }
+            }
+        }
+    }
+
+    /// The mipmap level that fits a pixel, so the texture doesn't shimmer when it's far away.
+    ///
+    /// * `dx`: How much the texture coordinates change from one pixel to the next one on X.
+    /// * `dy`: The same as `dx`, but on Y.
+    pub fn lod(&self, dx: Vec2, dy: Vec2) -> f32 {
+        let size = vec2(self.width as f32, self.height as f32);
+        // How many texels a pixel covers.
+        let footprint = dx
+            .component_mul(&size)
+            .norm()
+            .max(dy.component_mul(&size).norm());
+
+        footprint.max(1.0).log2()
+    }
+}
+
+impl MipLevel {
+    fn texel(&self, x: u32, y: u32) -> Color {
+        self.colors[(y * self.width + x) as usize]
+    }
+
+    fn nearest(&self, uv: Vec2, wrap: WrapMode) -> Color {
+        let x = wrap.wrap((uv.x * self.width as f32).floor() as i64, self.width);
+        let y = wrap.wrap((uv.y * self.height as f32).floor() as i64, self.height);
+        self.texel(x, y)
+    }
+
+    fn bilinear(&self, uv: Vec2, wrap: WrapMode) -> Color {
+        // The centers of the texels are at the middle of each one.
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = uv.y * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |dx: i64, dy: i64| {
+            self.texel(
+                wrap.wrap(x0 as i64 + dx, self.width),
+                wrap.wrap(y0 as i64 + dy, self.height),
+            )
+        };
+        let top = texel(0, 0).lerp(&texel(1, 0), tx);
+        let bottom = texel(0, 1).lerp(&texel(1, 1), tx);
+        top.lerp(&bottom, ty)
+    }
+
+    /// Creates the next mipmap level, averaging every 2x2 block of texels.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let colors = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let block = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+                    self.texel(
+                        (x * 2 + dx).min(self.width - 1),
+                        (y * 2 + dy).min(self.height - 1),
+                    )
+                });
+                let channel = |get: fn(&Color) -> u8| {
+                    (block.iter().map(|c| get(c) as u32).sum::<u32>() / 4) as u8
+                };
+
+                Color::new(channel(|c| c.r), channel(|c| c.g), channel(|c| c.b))
+            })
+            .collect();
+
+        MipLevel {
+            width,
+            height,
+            colors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 texture: red, green on top and blue, white on the bottom.
+    fn checker() -> Texture {
+        let colors = vec![
+            Color::new(255, 0, 0),
+            Color::new(0, 255, 0),
+            Color::new(0, 0, 255),
+            Color::new(255, 255, 255),
+        ];
+        Texture::from_colors(2, 2, colors)
+    }
+
+    #[test]
+    fn test_sampling_wraps_and_filters() {
+        let texture = checker();
+        let nearest = |wrap| Sampler {
+            wrap,
+            filter: Filter::Nearest,
+            mipmaps: false,
+        };
+
+        // The edges of the texture don't go out of bounds.
+        assert_eq!(texture.get_pixel_color(1.0, 1.0), Color::new(255, 255, 255));
+        assert_eq!(texture.get_pixel_color(-3.0, 0.0), Color::new(255, 0, 0));
+
+        let uv = vec2(1.25, 0.25);
+        let sample = |wrap| texture.sample(uv, 0.0, &nearest(wrap));
+        assert_eq!(sample(WrapMode::Repeat), Color::new(255, 0, 0));
+        assert_eq!(sample(WrapMode::Clamp), Color::new(0, 255, 0));
+        assert_eq!(sample(WrapMode::Mirror), Color::new(0, 255, 0));
+
+        // Right between the centers of red and green.
+        let bilinear = Sampler {
+            mipmaps: false,
+            ..Sampler::default()
+        };
+        assert_eq!(
+            texture.sample(vec2(0.5, 0.25), 0.0, &bilinear),
+            Color::new(128, 128, 0)
+        );
+    }
+
+    #[test]
+    fn test_mipmaps_average_the_texture() {
+        let texture = checker();
+        assert_eq!(texture.levels.len(), 2);
+        assert_eq!(texture.levels[1].colors, vec![Color::new(127, 127, 127)]);
+
+        // A pixel covering the whole texture uses the smallest level.
+        let lod = texture.lod(vec2(1.0, 0.0), vec2(0.0, 1.0));
+        assert_eq!(lod, 1.0);
+        assert_eq!(
+            texture.sample(vec2(0.1, 0.1), lod, &Sampler::default()),
+            Color::new(127, 127, 127)
+        );
+    }
+
+    #[test]
+    fn test_missing_baked_textures_arent_found() {
+        let mut textures = GameTextures::new("assets/textures/");
+        assert!(textures.get_texture(Textures::Baked(0)).is_none());
+
+        let baked = textures.add_baked(checker());
+        assert_eq!(textures.get_texture(baked).unwrap().width, 2);
+    }
+
+    #[test]
+    fn test_animated_frames_follow_the_time() {
+        use image::{codecs::gif::GifEncoder, Delay, RgbaImage};
+
+        // Red for 50ms, green for 150ms and blue for 100ms.
+        let frames = [([255, 0, 0], 5), ([0, 255, 0], 15), ([0, 0, 255], 10)].map(
+            |(color, centiseconds)| {
+                let [r, g, b] = color;
+                let buffer = RgbaImage::from_pixel(4, 4, image::Rgba([r, g, b, 255]));
+                Frame::from_parts(
+                    buffer,
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(centiseconds * 10, 1),
+                )
+            },
+        );
+        let file_path = std::env::temp_dir().join("gothspace_animated_test.gif");
+        GifEncoder::new(File::create(&file_path).unwrap())
+            .encode_frames(frames)
+            .unwrap();
+
+        let animation = AnimatedTexture::new(file_path.to_str().unwrap()).unwrap();
+        assert_eq!(animation.frame_count, 3);
+        assert_eq!(animation.get_pixel_color(1, 2, 2), Color::new(0, 255, 0));
+
+        let frame_at = |time, fps, looping| animation.frame_at(time, fps, looping);
+        assert_eq!(frame_at(0.0, None, true), 0);
+        assert_eq!(frame_at(60.0, None, true), 1);
+        assert_eq!(frame_at(250.0, None, true), 2);
+        assert_eq!(frame_at(310.0, None, true), 0);
+        assert_eq!(frame_at(310.0, None, false), 2);
+
+        // 10 FPS ignores the delays of the GIF.
+        assert_eq!(frame_at(150.0, Some(10.0), true), 1);
+        assert_eq!(frame_at(450.0, Some(10.0), true), 1);
+        assert_eq!(frame_at(450.0, Some(10.0), false), 2);
+    }
+
+    #[test]
+    fn test_gifs_without_frames_arent_loaded() {
+        use image::codecs::gif::GifEncoder;
 
-        // println!("({}, {}) -> ({}, {})", u, v, x, y);
+        let file_path = std::env::temp_dir().join("gothspace_empty_test.gif");
+        GifEncoder::new(File::create(&file_path).unwrap())
+            .encode_frames(std::iter::empty::<Frame>())
+            .unwrap();
 
-        let idx = y * self.width + x;
-        self.colors[idx as usize]
+        let mut textures = GameTextures::new("assets/textures/");
+        let file_path = file_path.to_str().unwrap();
+        assert!(textures.load_animated(file_path).is_err());
+        assert!(textures
+            .load_animated("assets/textures/missing.gif")
+            .is_err());
+        assert!(textures.animated.is_empty());
     }
 }
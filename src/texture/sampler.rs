@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+/// What happens with the texture coordinates outside of 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WrapMode {
+    /// The texture tiles.
+    Repeat,
+    /// The texels on the edges stretch forever.
+    Clamp,
+    /// The texture tiles, flipping every other copy.
+    Mirror,
+}
+
+impl WrapMode {
+    /// Moves the index of a texel inside `0..size`.
+    pub fn wrap(&self, idx: i64, size: u32) -> u32 {
+        let size = size as i64;
+        let idx = match self {
+            WrapMode::Repeat => idx.rem_euclid(size),
+            WrapMode::Clamp => idx.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let idx = idx.rem_euclid(2 * size);
+                if idx < size {
+                    idx
+                } else {
+                    2 * size - 1 - idx
+                }
+            }
+        };
+
+        idx as u32
+    }
+}
+
+/// How the texels around the texture coordinates get combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Filter {
+    /// Takes the closest texel, looks pixelated up close.
+    Nearest,
+    /// Blends the 4 closest texels, and the 2 closest mipmap levels.
+    Bilinear,
+}
+
+/// How a texture gets sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sampler {
+    pub wrap: WrapMode,
+    pub filter: Filter,
+    /// Uses smaller versions of the texture when it's far away, so it doesn't shimmer.
+    pub mipmaps: bool,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Sampler {
+            wrap: WrapMode::Repeat,
+            filter: Filter::Bilinear,
+            mipmaps: true,
+        }
+    }
+}
@@ -7,12 +7,17 @@ use nalgebra_glm::{Vec2, Vec3, Vec4};
 pub struct Vertex {
     /// The position of the vertex inside the 3D model.
     pub model_position: Vec3,
+    /// The position of the vertex inside the world.
+    pub world_position: Vec3,
     /// The position of the vertex inside the screen.
     pub screen_position: Vec3,
     /// The position of the vertex inside the camera frustum.
     /// Useful for checking if is inside the camera FOV.
     pub frustum_position: Vec4,
     pub normal: Vec3,
+    /// Direction in which the `u` texture coordinate grows, perpendicular to the normal.
+    /// Zero if the mesh doesn't have texture coordinates.
+    pub tangent: Vec3,
     pub tex_coords: Vec2,
     pub color: Color,
 }
@@ -22,7 +27,9 @@ impl Vertex {
         Vertex {
             screen_position: position,
             model_position: position,
+            world_position: position,
             normal,
+            tangent: Vec3::zeros(),
             tex_coords,
             color: Color::black(),
             frustum_position: Vec4::zeros(),
@@ -33,7 +40,9 @@ impl Vertex {
         Vertex {
             screen_position: position,
             model_position: position,
+            world_position: position,
             normal: Vec3::new(0.0, 0.0, 0.0),
+            tangent: Vec3::zeros(),
             tex_coords: Vec2::new(0.0, 0.0),
             color,
             frustum_position: Vec4::zeros(),
@@ -51,10 +60,12 @@ impl Default for Vertex {
         Vertex {
             screen_position: Vec3::zeros(),
             normal: Vec3::zeros(),
+            tangent: Vec3::zeros(),
             tex_coords: Vec2::zeros(),
             color: Color::black(),
             frustum_position: Vec4::zeros(),
             model_position: Vec3::zeros(),
+            world_position: Vec3::zeros(),
         }
     }
 }
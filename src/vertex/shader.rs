@@ -1,10 +1,24 @@
-use std::f32::consts::PI;
+use fastnoise_lite::{
+    CellularDistanceFunction, CellularReturnType, DomainWarpType, FastNoiseLite, FractalType,
+    NoiseType,
+};
+use nalgebra_glm::{
+    mat4_to_mat3, quat_angle_axis, quat_normalize, quat_to_mat4, vec3, vec4, Mat3, Mat4, Quat, Vec3,
+};
 
-use fastnoise_lite::{CellularDistanceFunction, CellularReturnType, FastNoiseLite, FractalType};
-use nalgebra_glm::{vec3, vec4, Mat4, Vec3};
+use serde::{Deserialize, Serialize};
 
-use crate::{texture::Textures, vertex::Vertex};
+use crate::{
+    color::ramp::ColorRamp,
+    scene::noise::{
+        CellularDistanceFunctionDef, CellularReturnTypeDef, DomainWarpTypeDef, FractalTypeDef,
+        NoiseTypeDef,
+    },
+    texture::{sampler::Sampler, Textures},
+    vertex::Vertex,
+};
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ShaderType {
     Stripe {
         stripe_width: f32,
@@ -25,51 +39,181 @@ pub enum ShaderType {
         zoom: f32,
         speed: f32,
         fractal: FractalConfig,
+        /// Colors the noise, without one the first color is multiplied by the noise.
+        #[serde(default)]
+        ramp: Option<ColorRamp>,
     },
     CellularShader {
         zoom: f32,
         speed: f32,
         fractal: FractalConfig,
         cellular: CellularConfig,
+        /// Colors the noise, without one the first color is multiplied by the noise.
+        #[serde(default)]
+        ramp: Option<ColorRamp>,
     },
     Texture {
         texture: Textures,
+        #[serde(default)]
+        sampler: Sampler,
+    },
+    /// Plays a GIF on the texture coordinates of the mesh, like a screen.
+    /// The GIF must be loaded with `GameTextures::load_animated`, if it isn't the layer is skipped.
+    AnimatedTexture {
+        /// Path of the GIF.
+        path: String,
+        /// Frames per second, if not set every frame lasts as long as the GIF says.
+        #[serde(default)]
+        fps: Option<f32>,
+        /// If false, the last frame stays once the animation ends.
+        #[serde(default = "default_looping")]
+        looping: bool,
+        #[serde(default)]
+        sampler: Sampler,
+    },
+    /// Lights the accumulated color using the `Material` of the entity.
+    Lighting,
+    /// A glow on the rim of the entity, like the atmosphere of a planet.
+    /// Uses the first color as the tint and is brighter on the side facing the lights.
+    /// Meant to be used with `BlendMode::Add` after `ShaderType::Lighting`.
+    Atmosphere {
+        /// How thick the atmosphere looks, multiplies the glow.
+        density: f32,
+        /// Bigger values push the glow towards the rim.
+        falloff: f32,
+    },
+    /// Concentric bands with gaps between them, like the rings of a planet.
+    /// Uses the `u` texture coordinate as the distance from the inner edge, like `create_ring`.
+    /// Cycles through the colors and sets the alpha of the fragment.
+    RingBands {
+        /// How many bands there are from the inner to the outer edge.
+        bands: f32,
+        /// Fraction of each band that's empty, from 0 to 1.
+        gap: f32,
+    },
+    /// Sets the alpha of the fragment from FBm noise, like clouds.
+    /// Only the fragments of transparent entities use the alpha.
+    NoiseAlpha {
+        zoom: f32,
+        speed: f32,
+        fractal: FractalConfig,
+        /// Noise values below this are fully transparent, from 0 to 1.
+        threshold: f32,
+        /// How wide the border between transparent and opaque is.
+        softness: f32,
+    },
+    /// A shader registered by name on the `ShaderRegistry` of the `Model`.
+    /// Layers whose shader isn't registered are skipped.
+    Custom {
+        name: String,
+    },
+    /// Perturbs the normal of the fragment using a tangent space normal map.
+    /// Only affects the stages that come after it, like `ShaderType::Lighting`.
+    NormalMap {
+        texture: Textures,
+        /// How much the normal map affects the normal, from 0 to 1.
+        strength: f32,
+    },
+    /// Perturbs the normal of the fragment following the slope of a noise function,
+    /// as if the noise was the height of the terrain.
+    /// Only affects the stages that come after it, like `ShaderType::Lighting`.
+    NoiseBump {
+        zoom: f32,
+        /// How tall the terrain is, in model units.
+        strength: f32,
+        fractal: FractalConfig,
     },
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct FractalConfig {
     pub octaves: i32,
     pub lacunarity: f32,
     pub gain: f32,
     pub weighted_strength: f32,
+    /// `FractalType::Ridged` gives sharp crests, like mountain ridges.
+    #[serde(with = "FractalTypeDef")]
     pub f_type: FractalType,
+    /// `ShaderType::CellularShader` always uses `NoiseType::Cellular`.
+    #[serde(with = "NoiseTypeDef", default = "default_noise_type")]
+    pub noise_type: NoiseType,
+    #[serde(default = "default_noise_seed")]
+    pub seed: i32,
+    /// Folds every octave of the noise into soft and puffy shapes, the opposite of `FractalType::Ridged`.
+    /// The octaves are summed like `FractalType::FBm`, whatever `f_type` is.
+    #[serde(default)]
+    pub billow: bool,
+    /// Distorts the position before sampling the noise, making it swirl.
+    #[serde(default)]
+    pub warp: Option<DomainWarpConfig>,
+    /// Samples the noise in 3D on the unit sphere around the model position,
+    /// instead of on its XY plane. Spheres get no seams nor pinched poles.
+    #[serde(default)]
+    pub spherical: bool,
+    /// Moves the noise around, so entities sharing a config don't look the same.
+    #[serde(default)]
+    pub offset: Vec3,
 }
 
-#[derive(Debug, Clone, Copy)]
+fn default_looping() -> bool {
+    true
+}
+
+fn default_noise_type() -> NoiseType {
+    NoiseType::OpenSimplex2
+}
+
+fn default_noise_seed() -> i32 {
+    NOISE_SEED
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DomainWarpConfig {
+    #[serde(with = "DomainWarpTypeDef")]
+    pub warp_type: DomainWarpType,
+    /// How far the position gets pushed, in the same units as the zoomed position.
+    pub amplitude: f32,
+    /// `FractalType::DomainWarpProgressive` and `FractalType::DomainWarpIndependent`
+    /// warp once per octave of the `FractalConfig`, anything else warps once.
+    #[serde(with = "FractalTypeDef")]
+    pub f_type: FractalType,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CellularConfig {
+    #[serde(with = "CellularDistanceFunctionDef")]
     pub distance_func: CellularDistanceFunction,
+    #[serde(with = "CellularReturnTypeDef")]
     pub return_type: CellularReturnType,
     pub jitter: f32,
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Uniforms {
+    /// Where the camera is located in world space.
+    pub camera_position: Vec3,
+    /// The model matrix of the entity being rendered.
+    pub model_matrix: Mat4,
     pub view_matrix: Mat4,
     pub projection_matrix: Mat4,
     pub viewport_matrix: Mat4,
     pub time: f32,
 }
 
+/// The seed used by noise layers that don't set one.
+pub const NOISE_SEED: i32 = 7082003;
+
 pub fn create_noise() -> FastNoiseLite {
-    let mut noise = FastNoiseLite::with_seed(7082003);
+    let mut noise = FastNoiseLite::with_seed(NOISE_SEED);
 
     noise.set_fractal_lacunarity(Some(0.530));
 
     noise
 }
 
-pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms, model_matrix: &Mat4) -> Vertex {
+pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
     let Uniforms {
+        model_matrix,
         view_matrix,
         projection_matrix,
         viewport_matrix,
@@ -82,7 +226,8 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms, model_matrix: &Mat4)
         vertex.model_position.z,
         1.0,
     );
-    let transformed = projection_matrix * view_matrix * model_matrix * position;
+    let world_position = model_matrix * position;
+    let transformed = projection_matrix * view_matrix * world_position;
 
     let w = transformed.w;
     let ndc_position = vec4(transformed.x / w, transformed.y / w, transformed.z / w, 1.0);
@@ -91,57 +236,34 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms, model_matrix: &Mat4)
     let transformed_position = vec3(screen_position.x, screen_position.y, screen_position.z);
 
     // Transform normal
-    let vertex_normal = vec4(vertex.normal.x, vertex.normal.y, vertex.normal.z, 1.0);
-    let normal_matrix = model_matrix
-        .try_inverse()
-        .unwrap_or(Mat4::identity())
-        .transpose();
-    let transformed_normal = normal_matrix * vertex_normal;
-    let w = transformed_normal.w;
-    let transformed_normal = vec3(
-        transformed_normal.x / w,
-        transformed_normal.y / w,
-        transformed_normal.z / w,
-    );
+    // Normals are directions, so the translation of the model doesn't affect them.
+    let transformed_normal = (create_normal_matrix(model_matrix) * vertex.normal).normalize();
+    // Tangents lie on the surface, so they follow the model matrix instead.
+    let transformed_tangent = (mat4_to_mat3(model_matrix) * vertex.tangent)
+        .try_normalize(f32::EPSILON)
+        .unwrap_or(Vec3::zeros());
 
     Vertex {
         screen_position: transformed_position,
+        world_position: world_position.xyz(),
         normal: transformed_normal,
+        tangent: transformed_tangent,
         frustum_position: ndc_position,
         ..*vertex
     }
 }
 
-pub fn create_model_matrix(translation: Vec3, scale: f32, rotation: Vec3) -> Mat4 {
-    let (sinx, cosx) = rotation.x.sin_cos();
-    let (siny, cosy) = rotation.y.sin_cos();
-    let (sinz, cosz) = rotation.z.sin_cos();
-
-    #[rustfmt::skip]
-    let rotation_x = Mat4::new(
-        1.0,    0.0,    0.0,    0.0,
-        0.0,    cosx,   -sinx,  0.0,
-        0.0,    sinx,   cosx,   0.0,
-        0.0,    0.0,    0.0,    1.0,
-    );
-
-    #[rustfmt::skip]
-    let rotation_y = Mat4::new(
-        cosy,   0.0,    siny,   0.0,
-        0.0,    1.0,    0.0,    0.0,
-        -siny,  0.0,    cosy,   0.0,
-        0.0,    0.0,    0.0,    1.0,
-    );
-
-    #[rustfmt::skip]
-    let rotation_z = Mat4::new(
-        cosz,   -sinz,  0.0,    0.0,
-        sinz,   cosz,   0.0,    0.0,
-        0.0,    0.0,    1.0,    0.0,
-        0.0,    0.0,    0.0,    1.0,
-    );
+/// Creates the matrix that moves normals from model space into world space.
+/// It's the inverse transpose of the rotation and scale part of the model matrix.
+pub fn create_normal_matrix(model_matrix: &Mat4) -> Mat3 {
+    mat4_to_mat3(model_matrix)
+        .try_inverse()
+        .unwrap_or(Mat3::identity())
+        .transpose()
+}
 
-    let rotation_matrix = rotation_z * rotation_y * rotation_x;
+pub fn create_model_matrix(translation: Vec3, scale: f32, orientation: Quat) -> Mat4 {
+    let rotation_matrix = quat_to_mat4(&orientation);
 
     #[rustfmt::skip]
     let matrix = Mat4::new(
@@ -154,12 +276,20 @@ pub fn create_model_matrix(translation: Vec3, scale: f32, rotation: Vec3) -> Mat
     matrix
 }
 
+/// Creates the orientation for the given euler angles, applied in Z*Y*X order.
+pub fn create_orientation(rotation: Vec3) -> Quat {
+    let x = quat_angle_axis(rotation.x, &Vec3::x());
+    let y = quat_angle_axis(rotation.y, &Vec3::y());
+    let z = quat_angle_axis(rotation.z, &Vec3::z());
+
+    quat_normalize(&(z * y * x))
+}
+
 pub fn create_view_matrix(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
     nalgebra_glm::look_at(&eye, &center, &up)
 }
 
-pub fn create_projection_matrix(window_width: f32, window_height: f32) -> Mat4 {
-    let fov = 45.0 * PI / 180.0;
+pub fn create_projection_matrix(window_width: f32, window_height: f32, fov: f32) -> Mat4 {
     let aspect_ratio = window_width / window_height;
     let near = 0.1;
     let far = 500.0;
@@ -168,12 +298,54 @@ pub fn create_projection_matrix(window_width: f32, window_height: f32) -> Mat4 {
 }
 
 pub fn create_viewport_matrix(framebuffer_width: f32, framebuffer_height: f32) -> Mat4 {
+    create_rect_viewport_matrix(0.0, 0.0, framebuffer_width, framebuffer_height)
+}
+
+/// Creates a viewport matrix that maps the NDC space into a rectangle of the framebuffer.
+///
+/// * `x`: Left side of the rectangle.
+/// * `y`: Top side of the rectangle.
+/// * `width`: Width of the rectangle.
+/// * `height`: Height of the rectangle.
+pub fn create_rect_viewport_matrix(x: f32, y: f32, width: f32, height: f32) -> Mat4 {
     #[rustfmt::skip]
     let matrix = Mat4::new(
-        framebuffer_width / 2.0,    0.0,                        0.0,    framebuffer_width / 2.0,
-        0.0,                        -framebuffer_height / 2.0,  0.0,    framebuffer_height / 2.0,
-        0.0,                        0.0,                        1.0,    0.0,
-        0.0,                        0.0,                        0.0,    1.0);
+        width / 2.0,    0.0,            0.0,    x + width / 2.0,
+        0.0,            -height / 2.0,  0.0,    y + height / 2.0,
+        0.0,            0.0,            1.0,    0.0,
+        0.0,            0.0,            0.0,    1.0);
 
     matrix
 }
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm::dot;
+
+    use super::*;
+    use crate::{equal, obj::load_objs};
+
+    #[test]
+    fn test_translated_sphere_normals_point_outwards() {
+        let objs = load_objs("assets/models/sphere.obj").unwrap();
+        let center = vec3(20.0, -3.0, 7.0);
+        let model_matrix =
+            create_model_matrix(center, 2.0, create_orientation(vec3(0.3, 1.2, -0.5)));
+        let uniforms = Uniforms {
+            camera_position: Vec3::zeros(),
+            model_matrix,
+            view_matrix: Mat4::identity(),
+            projection_matrix: Mat4::identity(),
+            viewport_matrix: Mat4::identity(),
+            time: 0.0,
+        };
+
+        for vertex in objs.iter().flatten() {
+            let transformed = vertex_shader(vertex, &uniforms);
+            let outwards = (transformed.world_position - center).normalize();
+
+            assert!(equal(transformed.normal.magnitude(), 1.0, 1e-4));
+            assert!(dot(&transformed.normal, &outwards) > 0.95);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use nalgebra_glm::{vec3, Mat4, Vec3};
+
+use crate::{
+    camera::Camera,
+    vertex::shader::{
+        create_projection_matrix, create_rect_viewport_matrix, create_view_matrix, Uniforms,
+    },
+    Model,
+};
+
+/// Height above the ship of the bird's eye inset camera.
+const BIRD_EYE_INSET_HEIGHT: f32 = 80.0;
+
+/// A rectangle inside the framebuffer, in pixels.
+/// The origin is located on the top left corner.
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Checks if the given point is inside the rectangle.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x as f32
+            && y >= self.y as f32
+            && x < (self.x + self.width) as f32
+            && y < (self.y + self.height) as f32
+    }
+}
+
+/// Which camera is used to render a viewport.
+#[derive(Debug, Clone, Copy)]
+pub enum ViewportCamera {
+    /// The camera of the `Model`.
+    Main,
+    /// Looks backwards from the ship, like a rear-view mirror.
+    RearView,
+    /// Looks down at the ship from above.
+    BirdEye,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    pub rect: Rect,
+    pub camera: ViewportCamera,
+    /// Insets clear the area they cover and get a border.
+    pub is_inset: bool,
+}
+
+/// How the framebuffer is split between viewports.
+#[derive(Debug, Clone, Copy)]
+pub enum ViewportLayout {
+    /// Only the main camera, using the whole framebuffer.
+    Single,
+    /// The main camera on the left half, the bird's eye camera on the right half.
+    SplitView,
+    /// A small rear-view mirror on the top of the screen.
+    RearViewMirror,
+    /// A small bird's eye view on the bottom right corner.
+    BirdEyeInset,
+}
+
+impl ViewportLayout {
+    /// Gets the layout that comes after this one.
+    pub fn next(&self) -> Self {
+        match self {
+            ViewportLayout::Single => ViewportLayout::SplitView,
+            ViewportLayout::SplitView => ViewportLayout::RearViewMirror,
+            ViewportLayout::RearViewMirror => ViewportLayout::BirdEyeInset,
+            ViewportLayout::BirdEyeInset => ViewportLayout::Single,
+        }
+    }
+
+    /// Computes the viewports of the layout for a framebuffer of the given size.
+    /// Viewports are ordered from back to front.
+    pub fn viewports(&self, width: usize, height: usize) -> Vec<Viewport> {
+        let full = Viewport {
+            rect: Rect::new(0, 0, width, height),
+            camera: ViewportCamera::Main,
+            is_inset: false,
+        };
+
+        match self {
+            ViewportLayout::Single => vec![full],
+            ViewportLayout::SplitView => {
+                let half = width / 2;
+                vec![
+                    Viewport {
+                        rect: Rect::new(0, 0, half, height),
+                        ..full
+                    },
+                    Viewport {
+                        rect: Rect::new(half, 0, width - half, height),
+                        camera: ViewportCamera::BirdEye,
+                        is_inset: false,
+                    },
+                ]
+            }
+            ViewportLayout::RearViewMirror => {
+                let (mirror_width, mirror_height) = (width / 3, height / 6);
+                vec![
+                    full,
+                    Viewport {
+                        rect: Rect::new(
+                            (width - mirror_width) / 2,
+                            10,
+                            mirror_width,
+                            mirror_height,
+                        ),
+                        camera: ViewportCamera::RearView,
+                        is_inset: true,
+                    },
+                ]
+            }
+            ViewportLayout::BirdEyeInset => {
+                let (inset_width, inset_height) = (width / 4, height / 4);
+                vec![
+                    full,
+                    Viewport {
+                        rect: Rect::new(
+                            width - inset_width - 10,
+                            height - inset_height - 10,
+                            inset_width,
+                            inset_height,
+                        ),
+                        camera: ViewportCamera::BirdEye,
+                        is_inset: true,
+                    },
+                ]
+            }
+        }
+    }
+}
+
+impl Viewport {
+    /// Computes the camera and uniforms used to render this viewport.
+    ///
+    /// * `data`: The model being rendered.
+    /// * `framebuffer_size`: The size of the whole framebuffer.
+    pub fn camera_and_uniforms(
+        &self,
+        data: &Model,
+        framebuffer_size: (usize, usize),
+    ) -> (Camera, Uniforms) {
+        let Model {
+            camera,
+            ship,
+            uniforms,
+            window_size: (window_width, window_height),
+            ..
+        } = data;
+        let ship_model = &ship.entity.model;
+
+        let camera = match self.camera {
+            ViewportCamera::Main => *camera,
+            ViewportCamera::RearView => {
+                let up = ship_model.up();
+                let eye = ship_model.translation + up;
+                let mut rear_camera =
+                    Camera::new(eye, eye - ship_model.forward(), up, camera.max_zoom);
+                // Mirrors show a wider area.
+                rear_camera.fov = camera.fov * 1.5;
+                rear_camera
+            }
+            ViewportCamera::BirdEye => Camera::new(
+                ship_model.translation + vec3(0.0, BIRD_EYE_INSET_HEIGHT, 0.0),
+                ship_model.translation,
+                Vec3::new(0.0, 0.0, -1.0),
+                camera.max_zoom,
+            ),
+        };
+
+        // The framebuffer gets stretched to fit the window,
+        // so the aspect ratio of the viewport depends on the window size.
+        let (framebuffer_width, framebuffer_height) = framebuffer_size;
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.rect;
+        let projection_width = *window_width as f32 * width as f32 / framebuffer_width as f32;
+        let projection_height = *window_height as f32 * height as f32 / framebuffer_height as f32;
+
+        let uniforms = Uniforms {
+            camera_position: camera.eye,
+            model_matrix: Mat4::identity(),
+            view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+            projection_matrix: create_projection_matrix(
+                projection_width,
+                projection_height,
+                camera.fov,
+            ),
+            viewport_matrix: create_rect_viewport_matrix(
+                x as f32,
+                y as f32,
+                width as f32,
+                height as f32,
+            ),
+            time: uniforms.time,
+        };
+
+        (camera, uniforms)
+    }
+}